@@ -0,0 +1,52 @@
+//! Digest algorithms that can be computed over labeled ranges.
+
+use sha2::Digest as _;
+
+pub use datalit_runtime::Checksum;
+
+/// A cryptographic digest, written out as its raw bytes.
+#[derive(Debug, Clone, Copy)]
+pub enum Digest {
+    Md5,
+    Sha1,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl Digest {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "md5" => Some(Digest::Md5),
+            "sha1" => Some(Digest::Sha1),
+            "sha224" => Some(Digest::Sha224),
+            "sha256" => Some(Digest::Sha256),
+            "sha384" => Some(Digest::Sha384),
+            "sha512" => Some(Digest::Sha512),
+            _ => None,
+        }
+    }
+
+    pub fn num_bytes(self) -> usize {
+        match self {
+            Digest::Md5 => 16,
+            Digest::Sha1 => 20,
+            Digest::Sha224 => 28,
+            Digest::Sha256 => 32,
+            Digest::Sha384 => 48,
+            Digest::Sha512 => 64,
+        }
+    }
+
+    pub fn compute(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Digest::Md5 => md5::Md5::digest(data).to_vec(),
+            Digest::Sha1 => sha1::Sha1::digest(data).to_vec(),
+            Digest::Sha224 => sha2::Sha224::digest(data).to_vec(),
+            Digest::Sha256 => sha2::Sha256::digest(data).to_vec(),
+            Digest::Sha384 => sha2::Sha384::digest(data).to_vec(),
+            Digest::Sha512 => sha2::Sha512::digest(data).to_vec(),
+        }
+    }
+}
@@ -1,5 +1,7 @@
+mod bits;
 mod block;
 mod call;
+mod interpolation;
 mod labeled;
 mod literal;
 mod mode_change;
@@ -9,10 +11,15 @@ mod sequence;
 use crate::state::{EntryState, StateOperation};
 
 pub use self::{
-    block::BlockEntry,
+    bits::BitsEntry,
+    block::{BlockEntry, SizedBlockEntry},
     call::CallEntry,
+    interpolation::InterpolationEntry,
     labeled::LabeledEntry,
-    literal::{ByteLiteral, ByteStringLiteral, CStringLiteral, IntLiteral},
+    literal::{
+        ByteLiteral, ByteStringLiteral, CStringLiteral, FloatConstant, FloatLiteral, IntLiteral,
+        StringLiteral,
+    },
     mode_change::ModeChange,
     repeat::RepeatEntry,
     sequence::SequenceEntry,
@@ -47,13 +54,34 @@ macro_rules! build_variant {
 build_variant! {
     enum Entry {
         (IntLiteral, "integer literal"),
+        (FloatLiteral, "float literal"),
+        (FloatConstant, "float constant"),
         (ByteStringLiteral, "byte string literal"),
         (ByteLiteral, "byte literal"),
         (CStringLiteral, "C-style string literal"),
+        (StringLiteral, "string literal"),
         (BlockEntry, "braced list of entries"),
+        (SizedBlockEntry, "sized block"),
+        (BitsEntry, "bit fields"),
         (LabeledEntry, "labeled entry"),
         (RepeatEntry, "repeated entry"),
         (CallEntry, "call entry"),
         (ModeChange, "mode change"),
+        (InterpolationEntry, "interpolated value"),
+    }
+}
+
+impl Entry {
+    /// Returns true if the entry is a block or repeat, whose element count is
+    /// available through `count('label)` when it is labeled. A label on a
+    /// labeled entry shares its count.
+    fn has_element_count(&self) -> bool {
+        matches!(
+            self,
+            Entry::BlockEntry(_)
+                | Entry::SizedBlockEntry(_)
+                | Entry::RepeatEntry(_)
+                | Entry::LabeledEntry(_)
+        )
     }
 }
@@ -0,0 +1,141 @@
+use proc_macro2::TokenStream;
+use quote::ToTokens;
+use syn::{
+    Error, Ident, LitInt,
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    token::{Brace, Paren},
+};
+
+use crate::{
+    entry::call::{Expr, ProcessCall as _, ValueKind, defer_write_int},
+    parse::base::FixedPrimitive,
+    state::{EntryState, StateOperation},
+    to_bytes::{BitOrder, MAX_BIT_FIELD_WIDTH},
+};
+
+/// A field of a bit group, such as `0b101: 3`.
+struct BitField {
+    value: Expr,
+    width: LitInt,
+}
+
+impl Parse for BitField {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let value: Expr = input.parse()?;
+        input.parse::<syn::Token![:]>()?;
+        let width: LitInt = input.parse()?;
+        if !width.suffix().is_empty() {
+            return Err(Error::new_spanned(
+                &width,
+                "suffixes are not allowed on bit field widths",
+            ));
+        }
+        Ok(Self { value, width })
+    }
+}
+
+impl BitField {
+    fn width(&self) -> syn::Result<usize> {
+        let width = self.width.base10_parse::<usize>()?;
+        if !(1..=MAX_BIT_FIELD_WIDTH).contains(&width) {
+            return Err(Error::new_spanned(
+                &self.width,
+                format!("Bit field width must be in the range 1..={MAX_BIT_FIELD_WIDTH}"),
+            ));
+        }
+        Ok(width)
+    }
+}
+
+/// Fields of arbitrary bit widths packed into whole bytes, such as
+/// `bits { 4: 4, 5: 4 }` (most significant bit first) or
+/// `bits(lsb) { ... }` (least significant bit first).
+pub struct BitsEntry {
+    tokens: TokenStream,
+    order: BitOrder,
+    fields: Punctuated<BitField, syn::Token![,]>,
+}
+
+impl BitsEntry {
+    pub fn peek(input: ParseStream) -> bool {
+        let fork = input.fork();
+        let is_bits = || -> syn::Result<bool> {
+            let name: Ident = fork.parse()?;
+            if fork.peek(Paren) {
+                let _content;
+                syn::parenthesized!(_content in fork);
+            }
+            Ok(name == "bits" && fork.peek(Brace))
+        };
+        input.peek(Ident) && is_bits().unwrap_or(false)
+    }
+}
+
+impl Parse for BitsEntry {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut tokens = TokenStream::new();
+        input.parse::<Ident>()?.to_tokens(&mut tokens);
+        let order = if input.peek(Paren) {
+            let args;
+            let paren = syn::parenthesized!(args in input);
+            let name: Ident = args.parse()?;
+            let _trailing: Option<syn::Token![,]> = args.parse()?;
+            paren.surround(&mut tokens, |tokens| name.to_tokens(tokens));
+            BitOrder::from_name(&name.to_string()).ok_or_else(|| {
+                Error::new_spanned(
+                    &name,
+                    format!("Unknown bit order: '{name}' (expected msb or lsb)"),
+                )
+            })?
+        } else {
+            BitOrder::MsbFirst
+        };
+        let content;
+        let brace = syn::braced!(content in input);
+        brace.surround(&mut tokens, |_| {});
+        let fields = Punctuated::parse_terminated(&content)?;
+        Ok(Self {
+            tokens,
+            order,
+            fields,
+        })
+    }
+}
+
+impl StateOperation for BitsEntry {
+    fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
+        let group_offset = state.curr_offset();
+        let mut offset = 0;
+        let mut fields = Vec::new();
+        for field in &self.fields {
+            let width = field.width()?;
+            let eval = field.value.process(state)?;
+            if eval.kind() != ValueKind::Int {
+                return Err(Error::new_spanned(
+                    &field.value,
+                    "Expressions producing bytes cannot be used as bit fields",
+                ));
+            }
+            let primitive = FixedPrimitive::bit_field(self.order, offset, width);
+            fields.push((eval, primitive, field.value.to_token_stream()));
+            offset += width;
+        }
+        if !offset.is_multiple_of(8) {
+            return Err(Error::new_spanned(
+                &self.tokens,
+                format!(
+                    "Bit fields total {offset} bits, which is not a whole number of bytes ({} more needed)",
+                    8 - offset % 8
+                ),
+            ));
+        }
+        // Each field is written into the group once its value is known, as
+        // values may depend on labels.
+        state.advance_bytes(offset / 8);
+        for (eval, primitive, expr_tokens) in fields {
+            defer_write_int(state, eval, primitive, group_offset, expr_tokens);
+        }
+        Ok(())
+    }
+}
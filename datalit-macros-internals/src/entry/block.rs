@@ -1,7 +1,11 @@
-use syn::token::Brace;
+use syn::{
+    Error, Ident, LitInt,
+    parse::{Parse, ParseStream},
+    token::{Brace, Paren},
+};
 
 use crate::{
-    entry::SequenceEntry,
+    entry::{SequenceEntry, mode_change::FillOverride},
     state::{EntryState, StateOperation},
 };
 
@@ -22,6 +26,73 @@ impl BlockEntry {
 
 impl StateOperation for BlockEntry {
     fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
-        self.entries.apply_to(state)
+        state.enter_scope();
+        self.entries.apply_to(state)?;
+        state.exit_scope();
+        state.report_element_count(self.entries.num_elements());
+        Ok(())
+    }
+}
+
+/// A block padded to a fixed size, such as `sized(512) { ... }`.
+pub struct SizedBlockEntry {
+    size: LitInt,
+    fill: FillOverride,
+    block: BlockEntry,
+}
+
+impl SizedBlockEntry {
+    pub fn peek(input: ParseStream) -> bool {
+        let fork = input.fork();
+        let is_sized_block = || -> syn::Result<bool> {
+            let name: Ident = fork.parse()?;
+            let _content;
+            syn::parenthesized!(_content in fork);
+            Ok(name == "sized" && fork.peek(Brace))
+        };
+        input.peek(Ident) && input.peek2(Paren) && is_sized_block().unwrap_or(false)
+    }
+}
+
+impl Parse for SizedBlockEntry {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let _name: Ident = input.parse()?;
+        let args;
+        syn::parenthesized!(args in input);
+        let size: LitInt = args.parse()?;
+        let fill = FillOverride::parse_arg(&args)?;
+        let _trailing: Option<syn::Token![,]> = args.parse()?;
+        let block: BlockEntry = input.parse()?;
+        Ok(Self { size, fill, block })
+    }
+}
+
+impl StateOperation for SizedBlockEntry {
+    fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
+        let fill = self.fill.fill_byte(state)?;
+        apply_padded(state, &self.block, &self.size, fill, "Block contents")
+    }
+}
+
+/// Applies `contents`, then pads them with `fill` to exactly `size` bytes.
+/// Contents larger than `size` are an error, described as `what`.
+pub(crate) fn apply_padded(
+    state: &mut EntryState,
+    contents: &dyn StateOperation,
+    size: &LitInt,
+    fill: u8,
+    what: &str,
+) -> syn::Result<()> {
+    let size_value = size.base10_parse::<usize>()?;
+    let start = state.curr_offset();
+    contents.apply_to(state)?;
+    let len = state.curr_offset() - start;
+    if len > size_value {
+        return Err(Error::new_spanned(
+            size,
+            format!("{what} ({len} bytes) overflow its size of {size_value} bytes"),
+        ));
     }
+    state.pad_bytes(size_value - len, fill);
+    Ok(())
 }
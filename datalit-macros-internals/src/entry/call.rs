@@ -1,4 +1,5 @@
 mod directives;
+mod expr;
 mod functions;
 
 use syn::parse::ParseStream;
@@ -7,6 +8,11 @@ use crate::state::{EntryState, StateOperation};
 
 use self::{directives::DirectiveEntry, functions::CallExprEntry};
 
+pub(crate) use self::{
+    expr::Expr,
+    functions::{ProcessCall, ValueKind, defer_write_int},
+};
+
 #[derive(derive_syn_parse::Parse)]
 pub enum CallEntry {
     #[peek_with(CallExprEntry::peek, name = "call expression")]
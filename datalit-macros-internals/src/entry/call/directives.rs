@@ -1,17 +1,28 @@
-use proc_macro2::Span;
+use std::path::Path;
+
+use num::ToPrimitive as _;
+
+use proc_macro2::{Span, TokenStream};
+use quote::{ToTokens as _, quote_spanned};
 use syn::{
-    Error, Ident,
+    Error, Ident, Lifetime, LitInt, LitStr,
     parse::{Parse, ParseStream},
     token::Paren,
 };
 
-use crate::state::{EntryState, StateOperation};
+use crate::{
+    entry::{
+        Entry, LabeledEntry, block::apply_padded, literal::parse_var_int_digits,
+        mode_change::FillOverride,
+    },
+    parse::{base::PrimitiveSpec, encoded::ByteEncoding, label_path::LabelPath},
+    state::{EntryState, StateOperation},
+    to_bytes::{StringEncoding, VarIntType},
+};
+
+use super::{expr::Expr, functions::CallExprEntry};
 
 pub struct DirectiveEntry {
-    #[expect(dead_code, reason = "Will shortly be implementing directives")]
-    name: Ident,
-    #[expect(dead_code, reason = "Will shortly be implementing directives")]
-    args: Paren,
     directive: Directive,
 }
 
@@ -25,13 +36,9 @@ impl Parse for DirectiveEntry {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let name: Ident = input.parse()?;
         let arg_content;
-        let args: Paren = syn::parenthesized!(arg_content in input);
+        syn::parenthesized!(arg_content in input);
         let directive = Directive::parse(name.span(), &name.to_string(), &arg_content)?;
-        Ok(Self {
-            name,
-            args,
-            directive,
-        })
+        Ok(Self { directive })
     }
 }
 
@@ -43,12 +50,36 @@ impl StateOperation for DirectiveEntry {
 
 pub enum Directive {
     Align(AlignDirective),
+    VarInt(VarIntDirective),
+    Include(IncludeDirective),
+    PadTo(PadToDirective),
+    Text(TextDirective),
+    PStr(PStrDirective),
+    Fixed(FixedDirective),
+    Decoded(DecodedDirective),
 }
 
 impl Directive {
     fn parse(call_span: Span, name: &str, args: ParseStream) -> syn::Result<Self> {
+        if let Some(encoding) = ByteEncoding::from_name(name) {
+            return Ok(Directive::Decoded(DecodedDirective::parse(encoding, args)?));
+        }
+        if let Some(encoding) = StringEncoding::from_name(name) {
+            return Ok(Directive::Text(TextDirective::parse(encoding, args)?));
+        }
+        if let Some(var_int_type) = VarIntType::from_suffix(name) {
+            return Ok(Directive::VarInt(VarIntDirective::parse(
+                var_int_type,
+                args,
+            )?));
+        }
         Ok(match name {
             "align" => Directive::Align(AlignDirective::parse(args)?),
+            "pad_to" | "org" => Directive::PadTo(PadToDirective::parse(args)?),
+            "include" => Directive::Include(IncludeDirective::parse(args)?),
+            "pstr" => Directive::PStr(PStrDirective::parse(call_span, args)?),
+            "fixed" => Directive::Fixed(FixedDirective::parse_fixed(args)?),
+            "zpad" => Directive::Fixed(FixedDirective::parse_zpad(args)?),
             _ => {
                 return Err(Error::new(
                     call_span,
@@ -63,6 +94,13 @@ impl StateOperation for Directive {
     fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
         match self {
             Directive::Align(align_directive) => align_directive.apply_to(state),
+            Directive::VarInt(var_int_directive) => var_int_directive.apply_to(state),
+            Directive::Include(include_directive) => include_directive.apply_to(state),
+            Directive::PadTo(pad_to_directive) => pad_to_directive.apply_to(state),
+            Directive::Text(text_directive) => text_directive.apply_to(state),
+            Directive::PStr(pstr_directive) => pstr_directive.apply_to(state),
+            Directive::Fixed(fixed_directive) => fixed_directive.apply_to(state),
+            Directive::Decoded(decoded_directive) => decoded_directive.apply_to(state),
         }
     }
 }
@@ -70,6 +108,8 @@ impl StateOperation for Directive {
 #[derive(derive_syn_parse::Parse)]
 pub struct AlignDirective {
     alignment: syn::LitInt,
+    #[call(FillOverride::parse_arg)]
+    fill: FillOverride,
     _trailing: Option<syn::Token![,]>,
 }
 
@@ -82,8 +122,345 @@ impl StateOperation for AlignDirective {
                 "Alignment must be a power of two",
             ));
         }
+        let fill = self.fill.fill_byte(state)?;
         let curr_offset = state.curr_offset();
-        state.advance_bytes(curr_offset.next_multiple_of(alignment) - curr_offset);
+        state.pad_bytes(curr_offset.next_multiple_of(alignment) - curr_offset, fill);
+        Ok(())
+    }
+}
+
+/// Pads the data up to an absolute offset, or an offset from the start of a
+/// label, such as `pad_to(0x40, from = 'sect)`.
+pub struct PadToDirective {
+    offset: Expr,
+    base: Option<LabelPath>,
+    fill: FillOverride,
+}
+
+impl Parse for PadToDirective {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let offset: Expr = input.parse()?;
+        let fork = input.fork();
+        let has_base = fork.parse::<syn::Token![,]>().is_ok()
+            && fork.parse::<Ident>().is_ok_and(|name| name == "from");
+        let base = if has_base {
+            input.parse::<syn::Token![,]>()?;
+            input.parse::<Ident>()?;
+            input.parse::<syn::Token![=]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+        let fill = FillOverride::parse_arg(input)?;
+        let _trailing: Option<syn::Token![,]> = input.parse()?;
+        Ok(Self { offset, base, fill })
+    }
+}
+
+impl StateOperation for PadToDirective {
+    fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
+        let value = self.offset.eval_const(state)?;
+        let offset = value.to_usize().ok_or_else(|| {
+            Error::new_spanned(
+                &self.offset,
+                format!("Offset must be a non-negative integer, not {value}"),
+            )
+        })?;
+        let base = match &self.base {
+            Some(base) => state.laid_out_label_start(base)?,
+            None => 0,
+        };
+        let fill = self.fill.fill_byte(state)?;
+        let curr_offset = state.curr_offset();
+        if curr_offset > base + offset {
+            let message = match &self.base {
+                Some(base_label) => format!(
+                    "Data is already {} bytes past the start of '{base_label}', past offset {offset}",
+                    curr_offset - base
+                ),
+                None => format!("Data is already {curr_offset} bytes long, past offset {offset}"),
+            };
+            return Err(Error::new_spanned(&self.offset, message));
+        }
+        state.pad_bytes(base + offset - curr_offset, fill);
+        Ok(())
+    }
+}
+
+pub struct VarIntDirective {
+    var_int_type: VarIntType,
+    value: syn::LitInt,
+}
+
+impl VarIntDirective {
+    fn parse(var_int_type: VarIntType, args: ParseStream) -> syn::Result<Self> {
+        let value: syn::LitInt = args.parse()?;
+        let _trailing: Option<syn::Token![,]> = args.parse()?;
+        Ok(Self {
+            var_int_type,
+            value,
+        })
+    }
+}
+
+impl StateOperation for VarIntDirective {
+    fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
+        if !self.value.suffix().is_empty() {
+            return Err(Error::new_spanned(
+                &self.value,
+                format!("suffixes are not allowed in {} values", self.var_int_type),
+            ));
+        }
+        let bytes = parse_var_int_digits(&self.value, self.var_int_type)?;
+        state.append_bytes(&bytes);
+        Ok(())
+    }
+}
+
+/// A string literal in an explicit encoding, such as `utf16le("name")`.
+pub struct TextDirective {
+    encoding: StringEncoding,
+    text: LitStr,
+}
+
+impl TextDirective {
+    fn parse(encoding: StringEncoding, args: ParseStream) -> syn::Result<Self> {
+        let text: LitStr = args.parse()?;
+        let _trailing: Option<syn::Token![,]> = args.parse()?;
+        Ok(Self { encoding, text })
+    }
+}
+
+impl StateOperation for TextDirective {
+    fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
+        let bytes = self.encoding.encode(&self.text, state.endian_mode())?;
+        state.append_bytes(&bytes);
+        Ok(())
+    }
+}
+
+/// Bytes written as text, such as `hex("de ad be ef")` or `base64("...")`,
+/// decoded when parsed.
+pub struct DecodedDirective {
+    data: Vec<u8>,
+}
+
+impl DecodedDirective {
+    fn parse(encoding: ByteEncoding, args: ParseStream) -> syn::Result<Self> {
+        let text: LitStr = args.parse()?;
+        let _trailing: Option<syn::Token![,]> = args.parse()?;
+        Ok(Self {
+            data: encoding.decode(&text)?,
+        })
+    }
+}
+
+impl StateOperation for DecodedDirective {
+    fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
+        state.append_bytes(&self.data);
+        Ok(())
+    }
+}
+
+/// An entry preceded by its length, such as `pstr(u8, b"abc")`.
+///
+/// This is the block `{ len('contents): u8, 'contents: b"abc" }`, so the
+/// length may be written as any type an expression can.
+pub struct PStrDirective {
+    prefix: CallExprEntry,
+    contents: LabeledEntry,
+}
+
+impl PStrDirective {
+    fn parse(call_span: Span, args: ParseStream) -> syn::Result<Self> {
+        let prefix_type: PrimitiveSpec = args.parse()?;
+        args.parse::<syn::Token![,]>()?;
+        let contents: Entry = args.parse()?;
+        let _trailing: Option<syn::Token![,]> = args.parse()?;
+        // The label is only visible within the block, so no other label can
+        // shadow it.
+        let label = Lifetime::new("'__datalit_pstr", call_span);
+        let prefix = syn::parse2(quote_spanned!(call_span=> len(#label): #prefix_type))?;
+        Ok(Self {
+            prefix,
+            contents: LabeledEntry::new(label, contents),
+        })
+    }
+}
+
+impl StateOperation for PStrDirective {
+    fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
+        state.enter_scope();
+        self.prefix.apply_to(state)?;
+        self.contents.apply_to(state)?;
+        state.exit_scope();
+        Ok(())
+    }
+}
+
+/// An entry padded to a fixed width, such as `fixed(16, b"name")` (padded
+/// with the fill byte) or `zpad(b"name", 16)` (padded with zeroes).
+pub struct FixedDirective {
+    size: LitInt,
+    contents: Box<Entry>,
+    /// The fill of `fixed`; `zpad` always pads with zeroes.
+    fill: Option<FillOverride>,
+}
+
+impl FixedDirective {
+    fn parse_fixed(args: ParseStream) -> syn::Result<Self> {
+        let size: LitInt = args.parse()?;
+        args.parse::<syn::Token![,]>()?;
+        let contents: Entry = args.parse()?;
+        let fill = FillOverride::parse_arg(args)?;
+        let _trailing: Option<syn::Token![,]> = args.parse()?;
+        Ok(Self {
+            size,
+            contents: Box::new(contents),
+            fill: Some(fill),
+        })
+    }
+
+    fn parse_zpad(args: ParseStream) -> syn::Result<Self> {
+        let contents: Entry = args.parse()?;
+        args.parse::<syn::Token![,]>()?;
+        let size: LitInt = args.parse()?;
+        let _trailing: Option<syn::Token![,]> = args.parse()?;
+        Ok(Self {
+            size,
+            contents: Box::new(contents),
+            fill: None,
+        })
+    }
+}
+
+impl StateOperation for FixedDirective {
+    fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
+        let fill = match &self.fill {
+            Some(fill) => fill.fill_byte(state)?,
+            None => 0x00,
+        };
+        apply_padded(state, &*self.contents, &self.size, fill, "Contents")
+    }
+}
+
+/// A byte range within an included file, such as `16..32`, `16..`, `..=31`.
+struct IncludeRange {
+    start: Option<LitInt>,
+    inclusive: bool,
+    end: Option<LitInt>,
+    tokens: TokenStream,
+}
+
+impl Parse for IncludeRange {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut tokens = TokenStream::new();
+        let start: Option<LitInt> = input.parse()?;
+        start.to_tokens(&mut tokens);
+        let inclusive = if input.peek(syn::Token![..=]) {
+            input.parse::<syn::Token![..=]>()?.to_tokens(&mut tokens);
+            true
+        } else {
+            input.parse::<syn::Token![..]>()?.to_tokens(&mut tokens);
+            false
+        };
+        let end: Option<LitInt> = input.parse()?;
+        end.to_tokens(&mut tokens);
+        if inclusive && end.is_none() {
+            return Err(Error::new_spanned(
+                &tokens,
+                "Inclusive ranges must have an end",
+            ));
+        }
+        Ok(Self {
+            start,
+            inclusive,
+            end,
+            tokens,
+        })
+    }
+}
+
+impl IncludeRange {
+    /// Resolves the range against a file of `file_len` bytes.
+    fn resolve(&self, file_len: usize) -> syn::Result<std::ops::Range<usize>> {
+        let start = match &self.start {
+            Some(start) => start.base10_parse::<usize>()?,
+            None => 0,
+        };
+        let end = match &self.end {
+            Some(end) if self.inclusive => end
+                .base10_parse::<usize>()?
+                .checked_add(1)
+                .ok_or_else(|| Error::new_spanned(end, "Range end is too large"))?,
+            Some(end) => end.base10_parse::<usize>()?,
+            None => file_len,
+        };
+        if start > end {
+            return Err(Error::new_spanned(
+                &self.tokens,
+                format!("Range start {start} is after its end {end}"),
+            ));
+        }
+        if end > file_len {
+            return Err(Error::new_spanned(
+                &self.tokens,
+                format!("Range end {end} is past the end of the file ({file_len} bytes)"),
+            ));
+        }
+        Ok(start..end)
+    }
+}
+
+/// Splices the contents of a file, resolved relative to the directory of the
+/// crate being compiled.
+pub struct IncludeDirective {
+    path: String,
+    data: Vec<u8>,
+}
+
+impl IncludeDirective {
+    fn parse(args: ParseStream) -> syn::Result<Self> {
+        let path_lit: LitStr = args.parse()?;
+        let range = if args.peek(syn::Token![,]) && !args.peek2(syn::parse::End) {
+            args.parse::<syn::Token![,]>()?;
+            Some(args.parse::<IncludeRange>()?)
+        } else {
+            None
+        };
+        let _trailing: Option<syn::Token![,]> = args.parse()?;
+
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").map_err(|_| {
+            Error::new_spanned(
+                &path_lit,
+                "CARGO_MANIFEST_DIR is not set, so included files cannot be resolved",
+            )
+        })?;
+        let path = Path::new(&manifest_dir).join(path_lit.value());
+        let contents = std::fs::read(&path).map_err(|e| {
+            Error::new_spanned(&path_lit, format!("Failed to read {}: {e}", path.display()))
+        })?;
+        let data = match range {
+            Some(range) => contents[range.resolve(contents.len())?].to_vec(),
+            None => contents,
+        };
+        let Some(path) = path.to_str() else {
+            return Err(Error::new_spanned(
+                &path_lit,
+                "Included paths must be valid UTF-8",
+            ));
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            data,
+        })
+    }
+}
+
+impl StateOperation for IncludeDirective {
+    fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
+        state.report_included_file(&self.path);
+        state.append_bytes(&self.data);
         Ok(())
     }
 }
@@ -0,0 +1,319 @@
+//! Integer expressions over label functions, such as `end('a) - start('b)`.
+
+use num::{ToPrimitive as _, Zero as _};
+use proc_macro2::TokenStream;
+use quote::ToTokens;
+use syn::{
+    Error, Ident, LitInt,
+    parse::{Parse, ParseStream},
+    token::Paren,
+};
+
+use crate::state::{EntryState, support::LocationMap};
+
+use super::functions::{EvalCallBox, FunctionExpr, ProcessCall, ValueKind};
+
+/// The largest shift amount accepted by `<<` and `>>`.
+const MAX_SHIFT: u32 = 1024;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum BinaryOpKind {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    BitAnd,
+    BitXor,
+    BitOr,
+}
+
+impl BinaryOpKind {
+    fn peek(input: ParseStream) -> Option<Self> {
+        // Check the two-character operators first, so that `<<` is not read
+        // as an unsupported `<`.
+        if input.peek(syn::Token![<<]) {
+            Some(BinaryOpKind::Shl)
+        } else if input.peek(syn::Token![>>]) {
+            Some(BinaryOpKind::Shr)
+        } else if input.peek(syn::Token![*]) {
+            Some(BinaryOpKind::Mul)
+        } else if input.peek(syn::Token![/]) {
+            Some(BinaryOpKind::Div)
+        } else if input.peek(syn::Token![%]) {
+            Some(BinaryOpKind::Rem)
+        } else if input.peek(syn::Token![+]) {
+            Some(BinaryOpKind::Add)
+        } else if input.peek(syn::Token![-]) {
+            Some(BinaryOpKind::Sub)
+        } else if input.peek(syn::Token![&]) {
+            Some(BinaryOpKind::BitAnd)
+        } else if input.peek(syn::Token![^]) {
+            Some(BinaryOpKind::BitXor)
+        } else if input.peek(syn::Token![|]) {
+            Some(BinaryOpKind::BitOr)
+        } else {
+            None
+        }
+    }
+
+    /// Binding strength, following Rust's operator precedence.
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOpKind::Mul | BinaryOpKind::Div | BinaryOpKind::Rem => 5,
+            BinaryOpKind::Add | BinaryOpKind::Sub => 4,
+            BinaryOpKind::Shl | BinaryOpKind::Shr => 3,
+            BinaryOpKind::BitAnd => 2,
+            BinaryOpKind::BitXor => 1,
+            BinaryOpKind::BitOr => 0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct BinaryOp {
+    kind: BinaryOpKind,
+    tokens: TokenStream,
+}
+
+impl BinaryOp {
+    fn parse(input: ParseStream, kind: BinaryOpKind) -> syn::Result<Self> {
+        let tokens = match kind {
+            BinaryOpKind::Mul => input.parse::<syn::Token![*]>()?.to_token_stream(),
+            BinaryOpKind::Div => input.parse::<syn::Token![/]>()?.to_token_stream(),
+            BinaryOpKind::Rem => input.parse::<syn::Token![%]>()?.to_token_stream(),
+            BinaryOpKind::Add => input.parse::<syn::Token![+]>()?.to_token_stream(),
+            BinaryOpKind::Sub => input.parse::<syn::Token![-]>()?.to_token_stream(),
+            BinaryOpKind::Shl => input.parse::<syn::Token![<<]>()?.to_token_stream(),
+            BinaryOpKind::Shr => input.parse::<syn::Token![>>]>()?.to_token_stream(),
+            BinaryOpKind::BitAnd => input.parse::<syn::Token![&]>()?.to_token_stream(),
+            BinaryOpKind::BitXor => input.parse::<syn::Token![^]>()?.to_token_stream(),
+            BinaryOpKind::BitOr => input.parse::<syn::Token![|]>()?.to_token_stream(),
+        };
+        Ok(Self { kind, tokens })
+    }
+
+    fn apply(
+        &self,
+        lhs: num::BigInt,
+        rhs: num::BigInt,
+        rhs_tokens: &TokenStream,
+    ) -> syn::Result<num::BigInt> {
+        let shift_amount = |rhs: &num::BigInt| {
+            rhs.to_u32()
+                .filter(|amount| *amount <= MAX_SHIFT)
+                .ok_or_else(|| {
+                    Error::new_spanned(
+                        rhs_tokens,
+                        format!("Shift amount {rhs} out of range (must be 0..={MAX_SHIFT})"),
+                    )
+                })
+        };
+        let nonzero = |rhs: &num::BigInt| {
+            if rhs.is_zero() {
+                Err(Error::new_spanned(rhs_tokens, "Division by zero"))
+            } else {
+                Ok(())
+            }
+        };
+        Ok(match self.kind {
+            BinaryOpKind::Mul => lhs * rhs,
+            BinaryOpKind::Div => {
+                nonzero(&rhs)?;
+                lhs / rhs
+            }
+            BinaryOpKind::Rem => {
+                nonzero(&rhs)?;
+                lhs % rhs
+            }
+            BinaryOpKind::Add => lhs + rhs,
+            BinaryOpKind::Sub => lhs - rhs,
+            BinaryOpKind::Shl => lhs << shift_amount(&rhs)?,
+            BinaryOpKind::Shr => lhs >> shift_amount(&rhs)?,
+            BinaryOpKind::BitAnd => lhs & rhs,
+            BinaryOpKind::BitXor => lhs ^ rhs,
+            BinaryOpKind::BitOr => lhs | rhs,
+        })
+    }
+}
+
+/// An integer-valued expression, evaluated once all labels are laid out.
+pub enum Expr {
+    Lit(LitInt),
+    /// A variable bound by a repeat, such as the `i` in `[i in 0..4 => i: u8]`.
+    Var(Ident),
+    Call(FunctionExpr),
+    Paren {
+        paren: Paren,
+        inner: Box<Expr>,
+    },
+    Binary {
+        lhs: Box<Expr>,
+        op: BinaryOp,
+        rhs: Box<Expr>,
+    },
+}
+
+impl Expr {
+    /// Returns true if the input starts with a binary operator.
+    pub fn peek_binary_op(input: ParseStream) -> bool {
+        BinaryOpKind::peek(input).is_some()
+    }
+
+    /// Returns true if the input starts with an integer literal followed by a
+    /// binary operator, such as `4 * len('x)`. This is an expression, not an
+    /// integer literal entry.
+    pub fn peek_leading_lit(input: ParseStream) -> bool {
+        let fork = input.fork();
+        fork.parse::<LitInt>().is_ok() && Self::peek_binary_op(&fork)
+    }
+
+    fn parse_atom(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(LitInt) {
+            let lit: LitInt = input.parse()?;
+            if !lit.suffix().is_empty() {
+                return Err(Error::new_spanned(
+                    &lit,
+                    "suffixes are not allowed on integers in expressions",
+                ));
+            }
+            Ok(Expr::Lit(lit))
+        } else if input.peek(Paren) {
+            let content;
+            let paren = syn::parenthesized!(content in input);
+            let inner: Expr = content.parse()?;
+            Ok(Expr::Paren {
+                paren,
+                inner: Box::new(inner),
+            })
+        } else if input.peek(Ident) && input.peek2(Paren) {
+            Ok(Expr::Call(input.parse()?))
+        } else if input.peek(Ident) {
+            Ok(Expr::Var(input.parse()?))
+        } else if input.peek(syn::Token![#]) {
+            Err(input.error(
+                "Interpolated values cannot be used within expressions, as the macro evaluates expressions itself, before the generated code computes interpolated values",
+            ))
+        } else {
+            Err(input.error(
+                "expected an expression (an integer, a variable, a function call, or a parenthesized expression)",
+            ))
+        }
+    }
+
+    fn parse_binary(input: ParseStream, min_precedence: u8) -> syn::Result<Self> {
+        let mut lhs = Self::parse_atom(input)?;
+        while let Some(kind) = BinaryOpKind::peek(input) {
+            if kind.precedence() < min_precedence {
+                break;
+            }
+            let op = BinaryOp::parse(input, kind)?;
+            let rhs = Self::parse_binary(input, kind.precedence() + 1)?;
+            lhs = Expr::Binary {
+                lhs: Box::new(lhs),
+                op,
+                rhs: Box::new(rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    /// Evaluates an expression whose value must be known while laying out the
+    /// data, such as a repeat count. It may not refer to labels.
+    pub fn eval_const(&self, state: &EntryState) -> syn::Result<num::BigInt> {
+        match self {
+            Expr::Lit(lit) => lit.base10_parse(),
+            Expr::Var(ident) => lookup_var(state, ident),
+            Expr::Call(call) => Err(Error::new_spanned(
+                call,
+                "Function calls cannot be used here, as the value must be known before labels are laid out",
+            )),
+            Expr::Paren { inner, .. } => inner.eval_const(state),
+            Expr::Binary { lhs, op, rhs } => {
+                let lhs_value = lhs.eval_const(state)?;
+                let rhs_value = rhs.eval_const(state)?;
+                op.apply(lhs_value, rhs_value, &rhs.to_token_stream())
+            }
+        }
+    }
+}
+
+fn lookup_var(state: &EntryState, ident: &Ident) -> syn::Result<num::BigInt> {
+    state.binding(&ident.to_string()).cloned().ok_or_else(|| {
+        Error::new_spanned(
+            ident,
+            format!("Unknown variable '{ident}' (variables are bound by repeats, e.g. `[{ident} in 0..4 => ...]`)"),
+        )
+    })
+}
+
+impl Parse for Expr {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Self::parse_binary(input, 0)
+    }
+}
+
+impl ToTokens for Expr {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            Expr::Lit(lit) => lit.to_tokens(tokens),
+            Expr::Var(ident) => ident.to_tokens(tokens),
+            Expr::Call(call) => call.to_tokens(tokens),
+            Expr::Paren { paren, inner } => {
+                paren.surround(tokens, |tokens| inner.to_tokens(tokens))
+            }
+            Expr::Binary { lhs, op, rhs } => {
+                lhs.to_tokens(tokens);
+                op.tokens.to_tokens(tokens);
+                rhs.to_tokens(tokens);
+            }
+        }
+    }
+}
+
+impl ProcessCall for Expr {
+    fn process(&self, state: &mut EntryState) -> syn::Result<EvalCallBox> {
+        match self {
+            Expr::Lit(lit) => {
+                let value: num::BigInt = lit.base10_parse()?;
+                Ok(EvalCallBox::new(move |_: &LocationMap| Ok(value.clone())))
+            }
+            Expr::Var(ident) => {
+                let value = lookup_var(state, ident)?;
+                Ok(EvalCallBox::new(move |_: &LocationMap| Ok(value.clone())))
+            }
+            Expr::Call(call) => call.process(state),
+            Expr::Paren { inner, .. } => inner.process(state),
+            Expr::Binary { lhs, op, rhs } => {
+                let lhs_eval = lhs.process(state)?;
+                let rhs_eval = rhs.process(state)?;
+                for (operand, eval) in [(lhs, &lhs_eval), (rhs, &rhs_eval)] {
+                    if eval.kind() != ValueKind::Int {
+                        return Err(Error::new_spanned(
+                            operand,
+                            "Expressions producing bytes cannot be used in arithmetic",
+                        ));
+                    }
+                }
+                let op = op.clone();
+                let rhs_tokens = rhs.to_token_stream();
+                let reads = lhs_eval
+                    .reads()
+                    .iter()
+                    .chain(rhs_eval.reads())
+                    .cloned()
+                    .collect();
+                Ok(EvalCallBox::reading_data(
+                    reads,
+                    move |location_map: &LocationMap, data: &[u8]| {
+                        let lhs = lhs_eval.eval_int(location_map, data)?;
+                        let rhs = rhs_eval.eval_int(location_map, data)?;
+                        op.apply(lhs, rhs, &rhs_tokens)
+                    },
+                ))
+            }
+        }
+    }
+}
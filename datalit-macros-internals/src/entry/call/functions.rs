@@ -1,86 +1,301 @@
+use proc_macro2::TokenStream;
+use quote::ToTokens;
 use syn::{
-    Error, Ident, Lifetime,
+    Error, Ident, LitInt,
     parse::{Parse, ParseStream},
     token::Paren,
 };
 
 use crate::{
-    parse::base::PrimitiveSpec,
-    state::{EntryState, StateOperation, support::LocationMap},
+    checksum::{Checksum, Digest},
+    parse::{
+        base::{FixedPrimitive, PrimitiveSpec, PrimitiveType},
+        label_path::LabelPath,
+    },
+    state::{
+        EntryState, StateOperation,
+        support::{DataRange, LabelRef, LocationMap},
+    },
 };
 
-#[derive(derive_syn_parse::Parse)]
+use super::expr::Expr;
+
 pub struct CallExprEntry {
-    call_expr: FunctionExpr,
-    #[prefix(syn::Token![:])]
-    primitive: PrimitiveSpec,
+    call_expr: Expr,
+    primitive: Option<PrimitiveSpec>,
 }
 
 impl CallExprEntry {
     pub fn peek(input: ParseStream) -> bool {
-        input.peek(Ident) && input.peek2(Paren) && input.peek3(syn::Token![:])
+        if input.peek(Paren) {
+            return true;
+        }
+        if input.peek(LitInt) {
+            return Expr::peek_leading_lit(input);
+        }
+        if input.peek(Ident) && !input.peek2(Paren) && !input.peek2(syn::Token![::]) {
+            // A variable followed by a type annotation or an operator.
+            let fork = input.fork();
+            let after_var = || -> syn::Result<bool> {
+                let _name: Ident = fork.parse()?;
+                Ok(fork.peek(syn::Token![:]) || Expr::peek_binary_op(&fork))
+            };
+            return after_var().unwrap_or(false);
+        }
+        if !(input.peek(Ident) && input.peek2(Paren)) {
+            return false;
+        }
+        // A call followed by a type annotation or an operator is an
+        // expression, as is any call producing bytes; otherwise it is a
+        // directive.
+        let fork = input.fork();
+        let after_call = || -> syn::Result<bool> {
+            let name: Ident = fork.parse()?;
+            let _content;
+            syn::parenthesized!(_content in fork);
+            Ok(fork.peek(syn::Token![:])
+                || Expr::peek_binary_op(&fork)
+                || Digest::from_name(&name.to_string()).is_some())
+        };
+        after_call().unwrap_or(false)
+    }
+
+    fn apply_int(
+        &self,
+        state: &mut EntryState,
+        eval: EvalCallBox,
+        primitive: &PrimitiveSpec,
+    ) -> syn::Result<()> {
+        let curr_offset = state.curr_offset();
+        // Range errors are reported on the expression that produced the value.
+        let expr_tokens = self.call_expr.to_token_stream();
+        let primitive = match primitive.prim_type() {
+            PrimitiveType::Fixed(primitive) => primitive,
+            PrimitiveType::Var(primitive) => {
+                if !eval.reads().is_empty() {
+                    return Err(Error::new_spanned(
+                        &self.call_expr,
+                        "Values computed from data cannot be written as variable-length integers",
+                    ));
+                }
+                state.defer_var_patch_op(move |location_map, min_len| {
+                    let value = eval.eval_int(location_map, &[])?;
+                    primitive
+                        .encode(&value, min_len)
+                        .map_err(|e| Error::new_spanned(&expr_tokens, e))
+                });
+                return Ok(());
+            }
+        };
+        state.advance_bytes(primitive.size());
+        defer_write_int(state, eval, primitive, curr_offset, expr_tokens);
+        Ok(())
+    }
+
+    fn apply_bytes(&self, state: &mut EntryState, eval: EvalCallBox, size: usize) {
+        let curr_offset = state.curr_offset();
+        state.advance_bytes(size);
+        let reads = eval.reads().to_vec();
+        let patch = move |location_map: &LocationMap, data: &mut [u8]| {
+            let Value::Bytes(bytes) = eval.eval(location_map, data)? else {
+                unreachable!("Expression kind was checked when processed");
+            };
+            assert_eq!(bytes.len(), size);
+            data[curr_offset..curr_offset + size].copy_from_slice(&bytes);
+            Ok(())
+        };
+        if reads.is_empty() {
+            state.defer_patch_op(patch);
+        } else {
+            let field = DataRange::new(curr_offset, curr_offset + size);
+            state.defer_data_patch_op(field, reads, self.call_expr.to_token_stream(), patch);
+        }
+    }
+}
+
+/// Defers writing the integer value of `eval` as `primitive` at `offset`, in
+/// space that has already been reserved. Range errors are reported on
+/// `expr_tokens`.
+pub(crate) fn defer_write_int(
+    state: &mut EntryState,
+    eval: EvalCallBox,
+    primitive: FixedPrimitive,
+    offset: usize,
+    expr_tokens: TokenStream,
+) {
+    let size = primitive.size();
+    let endian_mode = state.endian_mode();
+    let reads = eval.reads().to_vec();
+    let source = expr_tokens.clone();
+    let patch = move |location_map: &LocationMap, data: &mut [u8]| {
+        let value = eval.eval_int(location_map, data)?;
+        assert!(data.len() >= offset);
+        primitive
+            .write_int(endian_mode, &value, &mut data[offset..])
+            .map_err(|e| Error::new_spanned(&expr_tokens, e))?;
+        Ok(())
+    };
+    if reads.is_empty() {
+        state.defer_patch_op(patch);
+    } else {
+        let field = DataRange::new(offset, offset + size);
+        state.defer_data_patch_op(field, reads, source, patch);
+    }
+}
+
+impl Parse for CallExprEntry {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let call_expr: Expr = input.parse()?;
+        let primitive = if input.peek(syn::Token![:]) {
+            input.parse::<syn::Token![:]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+        Ok(Self {
+            call_expr,
+            primitive,
+        })
     }
 }
 
 impl StateOperation for CallExprEntry {
     fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
         let eval = self.call_expr.process(state)?;
-        let curr_offset = state.curr_offset();
-        let primitive = self.primitive.clone();
-        let endian_mode = state.endian_mode();
-        state.advance_bytes(primitive.int_type().num_bytes());
-        state.defer_patch_op(move |location_map, data| {
-            let value = eval.eval(location_map)?;
-            assert!(data.len() >= curr_offset);
-            primitive.write_int(endian_mode, &value, &mut data[curr_offset..])?;
-            Ok(())
-        });
-        Ok(())
+        match (eval.kind(), &self.primitive) {
+            (ValueKind::Int, Some(primitive)) => self.apply_int(state, eval, primitive),
+            (ValueKind::Int, None) => Err(Error::new_spanned(
+                &self.call_expr,
+                "Integer expressions must declare their output type (e.g. `len('x): u32`)",
+            )),
+            (ValueKind::Bytes(size), None) => {
+                self.apply_bytes(state, eval, size);
+                Ok(())
+            }
+            (ValueKind::Bytes(_), Some(primitive)) => Err(Error::new_spanned(
+                primitive,
+                "Expressions producing bytes cannot have an output type",
+            )),
+        }
     }
 }
 
+/// The result of evaluating an expression.
+pub enum Value {
+    Int(num::BigInt),
+    Bytes(Vec<u8>),
+}
+
+/// The kind of [`Value`] an expression produces, known before evaluation.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Int,
+    /// A byte array of the given length.
+    Bytes(usize),
+}
+
 trait EvalCall {
-    fn eval(&self, location_map: &LocationMap) -> syn::Result<num::BigInt>;
+    fn eval(&self, location_map: &LocationMap, data: &[u8]) -> syn::Result<Value>;
 }
 
 impl<F> EvalCall for F
 where
-    F: Fn(&LocationMap) -> syn::Result<num::BigInt> + 'static,
+    F: Fn(&LocationMap, &[u8]) -> syn::Result<Value> + 'static,
 {
-    fn eval(&self, location_map: &LocationMap) -> syn::Result<num::BigInt> {
-        (self)(location_map)
+    fn eval(&self, location_map: &LocationMap, data: &[u8]) -> syn::Result<Value> {
+        (self)(location_map, data)
     }
 }
 
-struct EvalCallBox(Box<dyn EvalCall>);
+pub struct EvalCallBox {
+    eval: Box<dyn EvalCall>,
+    kind: ValueKind,
+    reads: Vec<LabelRef>,
+}
 
 impl EvalCallBox {
-    fn new<F>(f: F) -> Self
+    /// Creates an integer evaluation that only depends on the label layout.
+    pub fn new<F>(f: F) -> Self
     where
         F: Fn(&LocationMap) -> syn::Result<num::BigInt> + 'static,
     {
-        Self(Box::new(f))
+        Self {
+            eval: Box::new(move |location_map: &LocationMap, _: &[u8]| {
+                f(location_map).map(Value::Int)
+            }),
+            kind: ValueKind::Int,
+            reads: Vec::new(),
+        }
+    }
+
+    /// Creates an integer evaluation that also reads the data within the
+    /// `reads` labels.
+    pub fn reading_data<F>(reads: Vec<LabelRef>, f: F) -> Self
+    where
+        F: Fn(&LocationMap, &[u8]) -> syn::Result<num::BigInt> + 'static,
+    {
+        Self {
+            eval: Box::new(move |location_map: &LocationMap, data: &[u8]| {
+                f(location_map, data).map(Value::Int)
+            }),
+            kind: ValueKind::Int,
+            reads,
+        }
+    }
+
+    /// Creates an evaluation producing `len` bytes from the data within the
+    /// `reads` labels.
+    pub fn bytes_reading_data<F>(len: usize, reads: Vec<LabelRef>, f: F) -> Self
+    where
+        F: Fn(&LocationMap, &[u8]) -> syn::Result<Vec<u8>> + 'static,
+    {
+        Self {
+            eval: Box::new(move |location_map: &LocationMap, data: &[u8]| {
+                f(location_map, data).map(Value::Bytes)
+            }),
+            kind: ValueKind::Bytes(len),
+            reads,
+        }
     }
 
-    fn eval(&self, location_map: &LocationMap) -> syn::Result<num::BigInt> {
-        self.0.eval(location_map)
+    pub fn kind(&self) -> ValueKind {
+        self.kind
+    }
+
+    /// The labels whose data this evaluation reads.
+    pub fn reads(&self) -> &[LabelRef] {
+        &self.reads
+    }
+
+    pub fn eval(&self, location_map: &LocationMap, data: &[u8]) -> syn::Result<Value> {
+        self.eval.eval(location_map, data)
+    }
+
+    /// Evaluates an expression already known to be of kind [`ValueKind::Int`].
+    pub fn eval_int(&self, location_map: &LocationMap, data: &[u8]) -> syn::Result<num::BigInt> {
+        match self.eval(location_map, data)? {
+            Value::Int(value) => Ok(value),
+            Value::Bytes(_) => unreachable!("Expression kind was checked when processed"),
+        }
     }
 }
 
-trait ProcessCall {
+pub trait ProcessCall {
     fn process(&self, state: &mut EntryState) -> syn::Result<EvalCallBox>;
 }
 
-struct FunctionExpr {
+pub struct FunctionExpr {
     name: Ident,
     args: Paren,
+    arg_tokens: TokenStream,
     func: FunctionCall,
 }
 
-impl FunctionExpr {
-    #[expect(dead_code, reason = "Will remove once we know we don't need it")]
-    pub fn span(&self) -> proc_macro2::Span {
-        self.name.span().join(self.args.span.join()).unwrap()
+impl ToTokens for FunctionExpr {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        self.name.to_tokens(tokens);
+        self.args
+            .surround(tokens, |tokens| self.arg_tokens.to_tokens(tokens));
     }
 }
 
@@ -89,10 +304,33 @@ impl Parse for FunctionExpr {
         let name: Ident = args.parse()?;
         let arg_content;
         let args: Paren = syn::parenthesized!(arg_content in args);
+        let arg_tokens: TokenStream = arg_content.fork().parse()?;
+        if let Some(checksum) = Checksum::from_name(&name.to_string()) {
+            let func = FunctionCall::Checksum(ChecksumCall::parse(checksum, &arg_content)?);
+            return Ok(Self {
+                name,
+                args,
+                arg_tokens,
+                func,
+            });
+        }
+        if let Some(digest) = Digest::from_name(&name.to_string()) {
+            let func = FunctionCall::Digest(DigestCall::parse(digest, &arg_content)?);
+            return Ok(Self {
+                name,
+                args,
+                arg_tokens,
+                func,
+            });
+        }
         let func = match name.to_string().as_str() {
             "start" => FunctionCall::Start(StartCall::parse(&arg_content)?),
             "end" => FunctionCall::End(EndCall::parse(&arg_content)?),
             "len" => FunctionCall::Len(LenCall::parse(&arg_content)?),
+            "count" => FunctionCall::Count(CountCall::parse(&arg_content)?),
+            "here" => FunctionCall::Here(HereCall::parse(&arg_content)?),
+            "rel" => FunctionCall::Rel(RelCall::parse(&arg_content)?),
+            "offset" => FunctionCall::Offset(OffsetCall::parse(&arg_content)?),
             _ => {
                 return Err(Error::new_spanned(
                     &name,
@@ -100,7 +338,12 @@ impl Parse for FunctionExpr {
                 ));
             }
         };
-        Ok(Self { name, args, func })
+        Ok(Self {
+            name,
+            args,
+            arg_tokens,
+            func,
+        })
     }
 }
 
@@ -114,6 +357,12 @@ enum FunctionCall {
     Start(StartCall),
     End(EndCall),
     Len(LenCall),
+    Count(CountCall),
+    Here(HereCall),
+    Rel(RelCall),
+    Offset(OffsetCall),
+    Checksum(ChecksumCall),
+    Digest(DigestCall),
 }
 
 impl ProcessCall for FunctionCall {
@@ -122,24 +371,30 @@ impl ProcessCall for FunctionCall {
             FunctionCall::Start(start_call) => start_call.process(state),
             FunctionCall::End(end_call) => end_call.process(state),
             FunctionCall::Len(len_call) => len_call.process(state),
+            FunctionCall::Count(count_call) => count_call.process(state),
+            FunctionCall::Here(here_call) => here_call.process(state),
+            FunctionCall::Rel(rel_call) => rel_call.process(state),
+            FunctionCall::Offset(offset_call) => offset_call.process(state),
+            FunctionCall::Checksum(checksum_call) => checksum_call.process(state),
+            FunctionCall::Digest(digest_call) => digest_call.process(state),
         }
     }
 }
 
 #[derive(derive_syn_parse::Parse)]
 pub struct StartCall {
-    lifetime: Lifetime,
+    label: LabelPath,
     _trailing: Option<syn::Token![,]>,
 }
 
 impl ProcessCall for StartCall {
     fn process(&self, state: &mut EntryState) -> syn::Result<EvalCallBox> {
-        state.report_label_use(&self.lifetime);
-        let lifetime_span = self.lifetime.span();
-        let name = self.lifetime.ident.to_string();
+        let label = state.report_label_use(&self.label);
+        let label_tokens = self.label.to_token_stream();
+        let name = self.label.to_string();
         Ok(EvalCallBox::new(move |location_map: &LocationMap| {
-            let range = location_map.get(&name).ok_or_else(|| {
-                Error::new(lifetime_span, format!("Label '{}' not defined", name))
+            let range = location_map.get(label).ok_or_else(|| {
+                Error::new_spanned(&label_tokens, format!("Label '{}' not defined", name))
             })?;
             Ok(range.start().into())
         }))
@@ -148,18 +403,18 @@ impl ProcessCall for StartCall {
 
 #[derive(derive_syn_parse::Parse)]
 pub struct EndCall {
-    lifetime: Lifetime,
+    label: LabelPath,
     _trailing: Option<syn::Token![,]>,
 }
 
 impl ProcessCall for EndCall {
     fn process(&self, state: &mut EntryState) -> syn::Result<EvalCallBox> {
-        state.report_label_use(&self.lifetime);
-        let lifetime_span = self.lifetime.span();
-        let name = self.lifetime.ident.to_string();
+        let label = state.report_label_use(&self.label);
+        let label_tokens = self.label.to_token_stream();
+        let name = self.label.to_string();
         Ok(EvalCallBox::new(move |location_map: &LocationMap| {
-            let range = location_map.get(&name).ok_or_else(|| {
-                Error::new(lifetime_span, format!("Label '{}' not defined", name))
+            let range = location_map.get(label).ok_or_else(|| {
+                Error::new_spanned(&label_tokens, format!("Label '{}' not defined", name))
             })?;
             Ok(range.end().into())
         }))
@@ -168,20 +423,207 @@ impl ProcessCall for EndCall {
 
 #[derive(derive_syn_parse::Parse)]
 pub struct LenCall {
-    lifetime: Lifetime,
+    label: LabelPath,
     _trailing: Option<syn::Token![,]>,
 }
 
 impl ProcessCall for LenCall {
     fn process(&self, state: &mut EntryState) -> syn::Result<EvalCallBox> {
-        state.report_label_use(&self.lifetime);
-        let lifetime_span = self.lifetime.span();
-        let name = self.lifetime.ident.to_string();
+        let label = state.report_label_use(&self.label);
+        let label_tokens = self.label.to_token_stream();
+        let name = self.label.to_string();
         Ok(EvalCallBox::new(move |location_map: &LocationMap| {
-            let range = location_map.get(&name).ok_or_else(|| {
-                Error::new(lifetime_span, format!("Label '{}' not defined", name))
+            let range = location_map.get(label).ok_or_else(|| {
+                Error::new_spanned(&label_tokens, format!("Label '{}' not defined", name))
             })?;
             Ok(range.size().into())
         }))
     }
 }
+
+/// The number of elements of a labeled block or repeat.
+#[derive(derive_syn_parse::Parse)]
+pub struct CountCall {
+    label: LabelPath,
+    _trailing: Option<syn::Token![,]>,
+}
+
+impl ProcessCall for CountCall {
+    fn process(&self, state: &mut EntryState) -> syn::Result<EvalCallBox> {
+        let label = state.report_label_use(&self.label);
+        let label_tokens = self.label.to_token_stream();
+        let name = self.label.to_string();
+        Ok(EvalCallBox::new(move |location_map: &LocationMap| {
+            let count = location_map.element_count(label).ok_or_else(|| {
+                Error::new_spanned(
+                    &label_tokens,
+                    format!(
+                        "Label '{}' does not label a block or array, so it has no count",
+                        name
+                    ),
+                )
+            })?;
+            Ok(count.into())
+        }))
+    }
+}
+
+/// The offset of the field the expression is written to.
+pub struct HereCall;
+
+impl HereCall {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if !input.is_empty() {
+            return Err(input.error("`here()` takes no arguments"));
+        }
+        Ok(Self)
+    }
+}
+
+impl ProcessCall for HereCall {
+    fn process(&self, state: &mut EntryState) -> syn::Result<EvalCallBox> {
+        // Expressions are processed just before their field is laid out.
+        let here = state.curr_offset();
+        Ok(EvalCallBox::new(move |_: &LocationMap| Ok(here.into())))
+    }
+}
+
+/// The start of a label relative to the field the expression is written to.
+#[derive(derive_syn_parse::Parse)]
+pub struct RelCall {
+    label: LabelPath,
+    _trailing: Option<syn::Token![,]>,
+}
+
+impl ProcessCall for RelCall {
+    fn process(&self, state: &mut EntryState) -> syn::Result<EvalCallBox> {
+        let here = num::BigInt::from(state.curr_offset());
+        let label = state.report_label_use(&self.label);
+        let label_tokens = self.label.to_token_stream();
+        let name = self.label.to_string();
+        Ok(EvalCallBox::new(move |location_map: &LocationMap| {
+            let range = location_map.get(label).ok_or_else(|| {
+                Error::new_spanned(&label_tokens, format!("Label '{}' not defined", name))
+            })?;
+            Ok(num::BigInt::from(range.start()) - &here)
+        }))
+    }
+}
+
+/// The start of a label relative to the start of another, such as
+/// `offset('target, from = 'base)`. Without a base, this is the same as
+/// `start('target)`.
+pub struct OffsetCall {
+    label: LabelPath,
+    base: Option<Box<LabelPath>>,
+}
+
+impl OffsetCall {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let label: LabelPath = input.parse()?;
+        let base = if input.peek(syn::Token![,]) && input.peek2(Ident) {
+            input.parse::<syn::Token![,]>()?;
+            let name: Ident = input.parse()?;
+            if name != "from" {
+                return Err(Error::new_spanned(
+                    &name,
+                    format!("Unknown argument: '{}'", name),
+                ));
+            }
+            input.parse::<syn::Token![=]>()?;
+            Some(Box::new(input.parse()?))
+        } else {
+            None
+        };
+        let _trailing: Option<syn::Token![,]> = input.parse()?;
+        Ok(Self { label, base })
+    }
+}
+
+impl ProcessCall for OffsetCall {
+    fn process(&self, state: &mut EntryState) -> syn::Result<EvalCallBox> {
+        let mut use_label = |path: &LabelPath| {
+            let label = state.report_label_use(path);
+            let label_tokens = path.to_token_stream();
+            let name = path.to_string();
+            move |location_map: &LocationMap| {
+                let range = location_map.get(label).ok_or_else(|| {
+                    Error::new_spanned(&label_tokens, format!("Label '{}' not defined", name))
+                })?;
+                Ok::<_, Error>(num::BigInt::from(range.start()))
+            }
+        };
+        let target_start = use_label(&self.label);
+        let base_start = self.base.as_deref().map(use_label);
+        Ok(EvalCallBox::new(move |location_map: &LocationMap| {
+            let target = target_start(location_map)?;
+            match &base_start {
+                Some(base_start) => Ok(target - base_start(location_map)?),
+                None => Ok(target),
+            }
+        }))
+    }
+}
+
+pub struct ChecksumCall {
+    checksum: Checksum,
+    label: LabelPath,
+}
+
+impl ChecksumCall {
+    fn parse(checksum: Checksum, input: ParseStream) -> syn::Result<Self> {
+        let label: LabelPath = input.parse()?;
+        let _trailing: Option<syn::Token![,]> = input.parse()?;
+        Ok(Self { checksum, label })
+    }
+}
+
+impl ProcessCall for ChecksumCall {
+    fn process(&self, state: &mut EntryState) -> syn::Result<EvalCallBox> {
+        let label = state.report_label_use(&self.label);
+        let checksum = self.checksum;
+        let label_tokens = self.label.to_token_stream();
+        let name = self.label.to_string();
+        Ok(EvalCallBox::reading_data(
+            vec![label],
+            move |location_map: &LocationMap, data: &[u8]| {
+                let range = location_map.get(label).ok_or_else(|| {
+                    Error::new_spanned(&label_tokens, format!("Label '{}' not defined", name))
+                })?;
+                Ok(checksum.compute(&data[range.start()..range.end()]).into())
+            },
+        ))
+    }
+}
+
+pub struct DigestCall {
+    digest: Digest,
+    label: LabelPath,
+}
+
+impl DigestCall {
+    fn parse(digest: Digest, input: ParseStream) -> syn::Result<Self> {
+        let label: LabelPath = input.parse()?;
+        let _trailing: Option<syn::Token![,]> = input.parse()?;
+        Ok(Self { digest, label })
+    }
+}
+
+impl ProcessCall for DigestCall {
+    fn process(&self, state: &mut EntryState) -> syn::Result<EvalCallBox> {
+        let label = state.report_label_use(&self.label);
+        let digest = self.digest;
+        let label_tokens = self.label.to_token_stream();
+        let name = self.label.to_string();
+        Ok(EvalCallBox::bytes_reading_data(
+            digest.num_bytes(),
+            vec![label],
+            move |location_map: &LocationMap, data: &[u8]| {
+                let range = location_map.get(label).ok_or_else(|| {
+                    Error::new_spanned(&label_tokens, format!("Label '{}' not defined", name))
+                })?;
+                Ok(digest.compute(&data[range.start()..range.end()]))
+            },
+        ))
+    }
+}
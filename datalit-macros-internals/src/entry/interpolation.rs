@@ -0,0 +1,169 @@
+use num::ToPrimitive as _;
+use proc_macro2::{Literal, TokenStream};
+use quote::{format_ident, quote_spanned};
+use syn::{
+    Error, Ident,
+    parse::{Parse, ParseStream},
+    token::Paren,
+};
+
+use crate::{
+    state::{EntryState, StateOperation},
+    to_bytes::{Endianness, FloatType, IntType, IntTypeExt as _},
+};
+
+/// A value computed from a Rust expression, such as `#(MAGIC): u32_le`.
+///
+/// The expression is evaluated in a const context by the generated code, so
+/// it may refer to constants from the surrounding crate.
+pub struct InterpolationEntry {
+    expr: TokenStream,
+    type_ident: Ident,
+}
+
+impl InterpolationEntry {
+    pub fn peek(input: ParseStream) -> bool {
+        input.peek(syn::Token![#]) && input.peek2(Paren)
+    }
+
+    /// Returns the Rust type of the value and how to write it.
+    fn value_type(&self) -> syn::Result<(ValueType, Option<Endianness>)> {
+        let ident_string = self.type_ident.to_string();
+        let mut suffix = ident_string.as_str();
+        let endianness = Endianness::parse_from_suffix(&mut suffix);
+
+        if let Some(float_type) = FloatType::from_suffix(suffix) {
+            return match float_type {
+                FloatType::F32 | FloatType::F64 => Ok((ValueType::Float(float_type), endianness)),
+                FloatType::F16 | FloatType::BF16 => Err(Error::new_spanned(
+                    &self.type_ident,
+                    format!("Interpolated values cannot be of type {float_type}"),
+                )),
+            };
+        }
+        let int_type = IntType::from_suffix(suffix).ok_or_else(|| {
+            Error::new_spanned(
+                &self.type_ident,
+                format!(
+                    "Invalid or missing integer type suffix: '{}'",
+                    self.type_ident
+                ),
+            )
+        })?;
+        // The macro only knows the pointer width of the host, which may not
+        // be that of the target the expression is evaluated for.
+        if suffix.ends_with("size") {
+            return Err(Error::new_spanned(
+                &self.type_ident,
+                format!(
+                    "Interpolated values cannot be of type {suffix}, as its width depends on the target; use a fixed width such as u64"
+                ),
+            ));
+        }
+        // Widths without a matching Rust type are read from the next wider
+        // type, and range checked.
+        let prefix = if int_type.is_signed() { 'i' } else { 'u' };
+        let rust_bits = int_type.num_bits().next_power_of_two();
+        let rust_type = format_ident!("{}{}", prefix, rust_bits);
+        let range_checked = rust_bits != int_type.num_bits();
+        Ok((
+            ValueType::Int {
+                int_type,
+                rust_type,
+                range_checked,
+            },
+            endianness,
+        ))
+    }
+}
+
+impl Parse for InterpolationEntry {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        input.parse::<syn::Token![#]>()?;
+        let content;
+        syn::parenthesized!(content in input);
+        let expr: TokenStream = content.parse()?;
+        if expr.is_empty() {
+            return Err(content.error("expected an expression"));
+        }
+        input.parse::<syn::Token![:]>()?;
+        let type_ident: Ident = input.parse()?;
+        Ok(Self { expr, type_ident })
+    }
+}
+
+impl StateOperation for InterpolationEntry {
+    fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
+        let (value_type, endianness) = self.value_type()?;
+        // Native values are written in the byte order of the host running the
+        // macro, as literals are, rather than that of the target.
+        let is_little = endianness.unwrap_or(state.endian_mode()).is_little();
+        let expr = &self.expr;
+        let type_span = self.type_ident.span();
+        let (size, read_value) = match value_type {
+            ValueType::Int {
+                int_type,
+                rust_type,
+                range_checked,
+            } => {
+                // Range-checked types are narrower than 128 bits, so their
+                // bounds fit in an `i128`.
+                let range_check = range_checked.then(|| {
+                    let min = Literal::i128_unsuffixed(int_type.min_value().to_i128().unwrap());
+                    let max = Literal::i128_unsuffixed(int_type.max_value().to_i128().unwrap());
+                    let message = format!("interpolated value out of range for {int_type}");
+                    quote_spanned! {type_span=>
+                        ::core::assert!(#min <= __value && __value <= #max, #message);
+                    }
+                });
+                (
+                    int_type.num_bytes(),
+                    quote_spanned! {type_span=>
+                        let __value: #rust_type = #expr;
+                        #range_check
+                        let __le_bytes = __value.to_le_bytes();
+                    },
+                )
+            }
+            ValueType::Float(float_type) => {
+                let rust_type = format_ident!("{}", float_type.to_string());
+                (
+                    float_type.num_bytes(),
+                    quote_spanned! {type_span=>
+                        let __value: #rust_type = #expr;
+                        let __le_bytes = __value.to_bits().to_le_bytes();
+                    },
+                )
+            }
+        };
+        let bytes_expr = quote_spanned! {self.type_ident.span()=>
+            {
+                #read_value
+                let mut __bytes = [0u8; #size];
+                let mut __i = 0;
+                while __i < #size {
+                    __bytes[__i] = if #is_little {
+                        __le_bytes[__i]
+                    } else {
+                        __le_bytes[#size - 1 - __i]
+                    };
+                    __i += 1;
+                }
+                __bytes
+            }
+        };
+        state.defer_interpolation(size, bytes_expr);
+        Ok(())
+    }
+}
+
+enum ValueType {
+    Int {
+        int_type: IntType,
+        /// The Rust type the value is read as.
+        rust_type: Ident,
+        /// Whether `rust_type` is wider than `int_type`.
+        range_checked: bool,
+    },
+    Float(FloatType),
+}
@@ -12,6 +12,13 @@ pub struct LabeledEntry {
 }
 
 impl LabeledEntry {
+    pub(crate) fn new(label: Lifetime, sub_entry: Entry) -> Self {
+        Self {
+            label,
+            sub_entry: Box::new(sub_entry),
+        }
+    }
+
     pub fn peek(input: ParseStream) -> bool {
         input.peek(Lifetime) && input.peek2(syn::Token![:])
     }
@@ -19,9 +26,12 @@ impl LabeledEntry {
 
 impl StateOperation for LabeledEntry {
     fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
-        let start = state.curr_offset();
+        let start = state.begin_label_def(&self.label)?;
         self.sub_entry.apply_to(state)?;
-        let end = state.curr_offset();
-        state.report_label_def(&self.label, start, end)
+        if !self.sub_entry.has_element_count() {
+            state.clear_element_count();
+        }
+        state.report_label_def(start);
+        Ok(())
     }
 }
@@ -1,9 +1,13 @@
 use quote::ToTokens;
-use syn::{Error, LitByte, LitByteStr, LitCStr, LitInt};
+use syn::{Error, Ident, LitByte, LitByteStr, LitCStr, LitFloat, LitInt, LitStr};
 
 use crate::{
+    entry::call::Expr,
     state::{EntryState, StateOperation},
-    to_bytes::{Endianness, IntType, base10_digits_to_bytes},
+    to_bytes::{
+        Endianness, FloatType, FloatTypeExt as _, IntType, VarIntType, VarIntTypeExt as _,
+        base10_digits_to_bytes,
+    },
 };
 
 fn parse_byte_literal<T>(
@@ -72,21 +76,25 @@ fn parse_int_literal(default_endianness: Endianness, lit: &LitInt) -> syn::Resul
         }
     }
 
-    let endianness = if suffix.ends_with("le") {
-        suffix = suffix.trim_end_matches("le");
-        suffix = suffix.trim_end_matches('_');
-        Endianness::Little
-    } else if suffix.ends_with("be") {
-        suffix = suffix.trim_end_matches("be");
-        suffix = suffix.trim_end_matches('_');
-        Endianness::Big
-    } else if suffix.ends_with("ne") {
-        suffix = suffix.trim_end_matches("ne");
-        suffix = suffix.trim_end_matches('_');
-        Endianness::Native
-    } else {
-        default_endianness
-    };
+    let explicit_endianness = Endianness::parse_from_suffix(&mut suffix);
+    let endianness = explicit_endianness.unwrap_or(default_endianness);
+
+    if let Some(var_int_type) = VarIntType::from_suffix(suffix) {
+        if explicit_endianness.is_some() {
+            return Err(Error::new_spanned(
+                lit,
+                format!("Variable-length type '{var_int_type}' cannot have an endianness"),
+            ));
+        }
+        return parse_var_int_digits(lit, var_int_type);
+    }
+
+    // Integer-looking literals with a float suffix (e.g. `1f32`) are floats.
+    if let Some(float_type) = FloatType::from_suffix(suffix) {
+        return float_type
+            .digits_to_bytes(lit.base10_digits(), endianness)
+            .map_err(|e| Error::new_spanned(lit, e));
+    }
 
     let int_type = IntType::from_suffix(suffix).ok_or_else(|| {
         Error::new_spanned(
@@ -98,6 +106,42 @@ fn parse_int_literal(default_endianness: Endianness, lit: &LitInt) -> syn::Resul
     base10_digits_to_bytes(lit.base10_digits(), int_type, endianness)
 }
 
+pub(crate) fn parse_var_int_digits(lit: &LitInt, var_int_type: VarIntType) -> syn::Result<Vec<u8>> {
+    let value: num::BigInt = lit.base10_parse()?;
+    var_int_type
+        .bigint_to_bytes(&value, 1)
+        .map_err(|e| Error::new_spanned(lit, e))
+}
+
+fn parse_float_type<T>(
+    err_context: &T,
+    full_suffix: &str,
+    default_endianness: Endianness,
+) -> syn::Result<(FloatType, Endianness)>
+where
+    T: ToTokens,
+{
+    let mut suffix = full_suffix;
+    let endianness = Endianness::parse_from_suffix(&mut suffix).unwrap_or(default_endianness);
+    let float_type = FloatType::from_suffix(suffix).ok_or_else(|| {
+        Error::new_spanned(
+            err_context,
+            format!(
+                "Invalid or missing float type suffix: '{}' (expected e.g. 'f32', 'f64_le')",
+                full_suffix
+            ),
+        )
+    })?;
+    Ok((float_type, endianness))
+}
+
+fn parse_float_literal(default_endianness: Endianness, lit: &LitFloat) -> syn::Result<Vec<u8>> {
+    let (float_type, endianness) = parse_float_type(lit, lit.suffix(), default_endianness)?;
+    float_type
+        .digits_to_bytes(lit.base10_digits(), endianness)
+        .map_err(|e| Error::new_spanned(lit, e))
+}
+
 #[derive(derive_syn_parse::Parse)]
 pub struct IntLiteral {
     value: LitInt,
@@ -105,7 +149,7 @@ pub struct IntLiteral {
 
 impl IntLiteral {
     pub fn peek(input: syn::parse::ParseStream) -> bool {
-        input.peek(LitInt)
+        input.peek(LitInt) && !Expr::peek_leading_lit(input)
     }
 }
 
@@ -117,6 +161,66 @@ impl StateOperation for IntLiteral {
     }
 }
 
+#[derive(derive_syn_parse::Parse)]
+pub struct FloatLiteral {
+    value: LitFloat,
+}
+
+impl FloatLiteral {
+    pub fn peek(input: syn::parse::ParseStream) -> bool {
+        input.peek(LitFloat)
+    }
+}
+
+impl StateOperation for FloatLiteral {
+    fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
+        let bytes = parse_float_literal(state.endian_mode(), &self.value)?;
+        state.append_bytes(&bytes);
+        Ok(())
+    }
+}
+
+/// A named special floating-point value, such as `f32::NAN` or
+/// `f64_be::NEG_INFINITY`.
+#[derive(derive_syn_parse::Parse)]
+pub struct FloatConstant {
+    float_type: Ident,
+    _colons: syn::Token![::],
+    name: Ident,
+}
+
+impl FloatConstant {
+    pub fn peek(input: syn::parse::ParseStream) -> bool {
+        input.peek(Ident) && input.peek2(syn::Token![::])
+    }
+}
+
+impl StateOperation for FloatConstant {
+    fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
+        let (float_type, endianness) = parse_float_type(
+            &self.float_type,
+            &self.float_type.to_string(),
+            state.endian_mode(),
+        )?;
+        let value = match self.name.to_string().as_str() {
+            "NAN" => f64::NAN,
+            "INFINITY" => f64::INFINITY,
+            "NEG_INFINITY" => f64::NEG_INFINITY,
+            name => {
+                return Err(Error::new_spanned(
+                    &self.name,
+                    format!(
+                        "Unknown float constant: '{}' (expected NAN, INFINITY or NEG_INFINITY)",
+                        name
+                    ),
+                ));
+            }
+        };
+        state.append_bytes(&float_type.encode(value, endianness));
+        Ok(())
+    }
+}
+
 #[derive(derive_syn_parse::Parse)]
 pub struct ByteLiteral {
     value: LitByte,
@@ -172,24 +276,56 @@ impl StateOperation for CStringLiteral {
     }
 }
 
+/// A text string literal, encoded with the `@string_encoding` mode (UTF-8 by
+/// default).
+#[derive(derive_syn_parse::Parse)]
+pub struct StringLiteral {
+    value: LitStr,
+}
+
+impl StringLiteral {
+    pub fn peek(input: syn::parse::ParseStream) -> bool {
+        input.peek(LitStr)
+    }
+}
+
+impl StateOperation for StringLiteral {
+    fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
+        let bytes = state
+            .string_encoding()
+            .encode(&self.value, state.endian_mode())?;
+        state.append_bytes(&bytes);
+        Ok(())
+    }
+}
+
 #[derive(derive_syn_parse::Parse)]
 pub enum LiteralEntry {
     #[peek_with(IntLiteral::peek, name = "integer literal")]
     Int(IntLiteral),
+    #[peek_with(FloatLiteral::peek, name = "float literal")]
+    Float(FloatLiteral),
+    #[peek_with(FloatConstant::peek, name = "float constant")]
+    FloatConst(FloatConstant),
     #[peek_with(ByteLiteral::peek, name = "byte literal")]
     Byte(ByteLiteral),
     #[peek_with(ByteStringLiteral::peek, name = "byte string literal")]
     ByteString(ByteStringLiteral),
     #[peek_with(CStringLiteral::peek, name = "C string literal")]
     CString(CStringLiteral),
+    #[peek_with(StringLiteral::peek, name = "string literal")]
+    String(StringLiteral),
 }
 
 impl LiteralEntry {
     pub fn peek(input: syn::parse::ParseStream) -> bool {
         IntLiteral::peek(input)
+            || FloatLiteral::peek(input)
+            || FloatConstant::peek(input)
             || ByteLiteral::peek(input)
             || ByteStringLiteral::peek(input)
             || CStringLiteral::peek(input)
+            || StringLiteral::peek(input)
     }
 }
 
@@ -197,9 +333,12 @@ impl StateOperation for LiteralEntry {
     fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
         match self {
             LiteralEntry::Int(int_lit) => int_lit.apply_to(state),
+            LiteralEntry::Float(float_lit) => float_lit.apply_to(state),
+            LiteralEntry::FloatConst(float_const) => float_const.apply_to(state),
             LiteralEntry::Byte(byte_lit) => byte_lit.apply_to(state),
             LiteralEntry::ByteString(byte_str_lit) => byte_str_lit.apply_to(state),
             LiteralEntry::CString(cstr_lit) => cstr_lit.apply_to(state),
+            LiteralEntry::String(str_lit) => str_lit.apply_to(state),
         }
     }
 }
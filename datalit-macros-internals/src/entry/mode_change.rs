@@ -1,8 +1,13 @@
-use syn::{Error, Ident, parse::ParseStream};
+use proc_macro2::TokenStream;
+use quote::ToTokens;
+use syn::{
+    Error, Ident, LitInt,
+    parse::{Parse, ParseStream},
+};
 
 use crate::{
     state::{EntryState, StateOperation},
-    to_bytes::Endianness,
+    to_bytes::{Endianness, StringEncoding},
 };
 
 #[derive(derive_syn_parse::Parse)]
@@ -10,38 +15,181 @@ pub struct ModeChange {
     #[prefix(syn::Token![@])]
     mode: Ident,
     #[prefix(syn::Token![=])]
-    new_mode: Ident,
+    new_mode: ModeValue,
 }
 
 impl ModeChange {
     pub fn peek(input: ParseStream) -> bool {
         input.peek(syn::Token![@]) && input.peek2(Ident) && input.peek3(syn::Token![=])
     }
+
+    fn apply_endian(&self, state: &mut EntryState) -> syn::Result<()> {
+        let new_mode = match &self.new_mode {
+            ModeValue::Ident(ident) if ident == "le" => Endianness::Little,
+            ModeValue::Ident(ident) if ident == "be" => Endianness::Big,
+            ModeValue::Ident(ident) if ident == "ne" => Endianness::Native,
+            new_mode => {
+                return Err(Error::new_spanned(
+                    new_mode,
+                    format!("Invalid endian mode: '{}'", new_mode.to_token_stream()),
+                ));
+            }
+        };
+        state.set_endian_mode(new_mode);
+        Ok(())
+    }
+
+    fn apply_string_encoding(&self, state: &mut EntryState) -> syn::Result<()> {
+        let encoding = match &self.new_mode {
+            ModeValue::Ident(ident) => StringEncoding::from_name(&ident.to_string()),
+            ModeValue::Int(_) => None,
+        };
+        let Some(encoding) = encoding else {
+            return Err(Error::new_spanned(
+                &self.new_mode,
+                format!(
+                    "Invalid string encoding: '{}' (expected e.g. utf8, utf16le, utf32be or latin1)",
+                    self.new_mode.to_token_stream()
+                ),
+            ));
+        };
+        state.set_string_encoding(encoding);
+        Ok(())
+    }
+
+    fn apply_fill(&self, state: &mut EntryState) -> syn::Result<()> {
+        let ModeValue::Int(fill) = &self.new_mode else {
+            return Err(Error::new_spanned(
+                &self.new_mode,
+                "Fill mode must be a byte value (e.g. `@fill = 0xFF`)",
+            ));
+        };
+        state.set_fill_byte(parse_fill_byte(fill)?);
+        Ok(())
+    }
+
+    fn apply_align_output(&self, state: &mut EntryState) -> syn::Result<()> {
+        let ModeValue::Int(alignment_lit) = &self.new_mode else {
+            return Err(Error::new_spanned(
+                &self.new_mode,
+                "Output alignment must be an integer (e.g. `@align_output = 16`)",
+            ));
+        };
+        // Within a block or array the mode would apply to the whole output
+        // anyway, and be applied again by each iteration of an array.
+        if !state.at_top_level() {
+            return Err(Error::new_spanned(
+                self,
+                "Output alignment can only be set at the top level, not within a block or array",
+            ));
+        }
+        let alignment = alignment_lit.base10_parse::<usize>()?;
+        if !alignment.is_power_of_two() || alignment > MAX_OUTPUT_ALIGNMENT {
+            return Err(Error::new_spanned(
+                alignment_lit,
+                format!("Output alignment must be a power of two up to {MAX_OUTPUT_ALIGNMENT}"),
+            ));
+        }
+        state.set_output_alignment(alignment, self.to_token_stream())
+    }
 }
 
+/// The largest alignment accepted by `#[repr(align(N))]`.
+const MAX_OUTPUT_ALIGNMENT: usize = 1 << 29;
+
 impl StateOperation for ModeChange {
     fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
         let mode_str = self.mode.to_string();
-        if mode_str != "endian" {
-            return Err(Error::new_spanned(
+        match mode_str.as_str() {
+            "endian" => self.apply_endian(state),
+            "fill" => self.apply_fill(state),
+            "string_encoding" => self.apply_string_encoding(state),
+            "align_output" => self.apply_align_output(state),
+            _ => Err(Error::new_spanned(
                 &self.mode,
                 format!("Unknown mode: '{}'", mode_str),
+            )),
+        }
+    }
+}
+
+impl ToTokens for ModeChange {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        syn::Token![@](self.mode.span()).to_tokens(tokens);
+        self.mode.to_tokens(tokens);
+        syn::Token![=](self.mode.span()).to_tokens(tokens);
+        self.new_mode.to_tokens(tokens);
+    }
+}
+
+/// The value assigned to a mode, e.g. `le` in `@endian = le` or `0xFF` in
+/// `@fill = 0xFF`.
+enum ModeValue {
+    Ident(Ident),
+    Int(LitInt),
+}
+
+impl Parse for ModeValue {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(LitInt) {
+            Ok(ModeValue::Int(input.parse()?))
+        } else {
+            Ok(ModeValue::Ident(input.parse()?))
+        }
+    }
+}
+
+impl ToTokens for ModeValue {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            ModeValue::Ident(ident) => ident.to_tokens(tokens),
+            ModeValue::Int(lit) => lit.to_tokens(tokens),
+        }
+    }
+}
+
+/// An optional trailing `fill = 0xFF` argument, overriding the `@fill` mode.
+pub(crate) struct FillOverride {
+    fill: Option<LitInt>,
+}
+
+impl FillOverride {
+    /// Parses `, fill = <byte>` if present, leaving any trailing comma.
+    pub(crate) fn parse_arg(input: ParseStream) -> syn::Result<Self> {
+        if !(input.peek(syn::Token![,]) && input.peek2(Ident)) {
+            return Ok(Self { fill: None });
+        }
+        input.parse::<syn::Token![,]>()?;
+        let name: Ident = input.parse()?;
+        if name != "fill" {
+            return Err(Error::new_spanned(
+                &name,
+                format!("Unknown argument: '{}'", name),
             ));
         }
+        input.parse::<syn::Token![=]>()?;
+        Ok(Self {
+            fill: Some(input.parse()?),
+        })
+    }
 
-        let new_mode_str = self.new_mode.to_string();
-        let new_mode = match new_mode_str.as_str() {
-            "le" => Endianness::Little,
-            "be" => Endianness::Big,
-            "ne" => Endianness::Native,
-            _ => {
-                return Err(Error::new_spanned(
-                    &self.new_mode,
-                    format!("Invalid endian mode: '{}'", new_mode_str),
-                ));
-            }
-        };
-        state.set_endian_mode(new_mode);
-        Ok(())
+    /// Returns the overridden fill byte, or the current fill mode.
+    pub(crate) fn fill_byte(&self, state: &EntryState) -> syn::Result<u8> {
+        match &self.fill {
+            Some(fill) => parse_fill_byte(fill),
+            None => Ok(state.fill_byte()),
+        }
+    }
+}
+
+/// Parses the byte used to fill padding.
+fn parse_fill_byte(lit: &LitInt) -> syn::Result<u8> {
+    if !lit.suffix().is_empty() {
+        return Err(Error::new_spanned(
+            lit,
+            "suffixes are not allowed on fill bytes",
+        ));
     }
+    lit.base10_parse::<u8>()
+        .map_err(|_| Error::new_spanned(lit, "Fill byte must be in the range 0..=255"))
 }
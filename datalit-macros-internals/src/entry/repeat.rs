@@ -1,8 +1,13 @@
-use syn::token::Bracket;
+use num::ToPrimitive as _;
+use syn::{
+    Ident,
+    parse::{Parse, ParseStream},
+    token::Bracket,
+};
 
 use crate::{
-    entry::{BlockEntry, literal::LiteralEntry},
-    state::StateOperation,
+    entry::{BlockEntry, Entry, call::Expr, literal::LiteralEntry},
+    state::{EntryState, StateOperation},
 };
 
 #[derive(derive_syn_parse::Parse)]
@@ -22,37 +27,163 @@ impl StateOperation for Contents {
     }
 }
 
-#[derive(derive_syn_parse::Parse)]
+/// The range of values bound to the variable of a repeat, such as `0..16` or
+/// `1..=4`.
+struct RepeatRange {
+    start: Expr,
+    inclusive: bool,
+    end: Expr,
+}
+
+impl Parse for RepeatRange {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let start: Expr = input.parse()?;
+        let inclusive = if input.peek(syn::Token![..=]) {
+            input.parse::<syn::Token![..=]>()?;
+            true
+        } else {
+            input.parse::<syn::Token![..]>()?;
+            false
+        };
+        let end: Expr = input.parse()?;
+        Ok(Self {
+            start,
+            inclusive,
+            end,
+        })
+    }
+}
+
+impl RepeatRange {
+    /// Returns the first value, and the number of values in the range.
+    fn resolve(&self, state: &EntryState) -> syn::Result<(num::BigInt, usize)> {
+        let start = self.start.eval_const(state)?;
+        let mut end = self.end.eval_const(state)?;
+        if self.inclusive {
+            end += 1;
+        }
+        let count = (&end - &start).max(num::BigInt::ZERO);
+        let count = count.to_usize().ok_or_else(|| {
+            syn::Error::new_spanned(&self.end, format!("Repeat range is too long ({count})"))
+        })?;
+        Ok((start, count))
+    }
+}
+
+enum Repeat {
+    /// `[entry; count]`
+    Count { contents: Contents, count: Expr },
+    /// `[i in 0..4 => entry]` or `[for i in 0..4 { entries }]`, repeating the
+    /// body with `i` bound to each value of the range in turn.
+    Range {
+        var: Ident,
+        range: RepeatRange,
+        body: Box<Entry>,
+    },
+}
+
 pub struct RepeatEntry {
-    #[bracket]
-    _brackets: Bracket,
-    #[inside(_brackets)]
-    contents: Contents,
-    #[prefix(syn::Token![;] in _brackets)]
-    #[inside(_brackets)]
-    count: syn::LitInt,
+    repeat: Repeat,
 }
 
 impl RepeatEntry {
     pub fn peek(input: syn::parse::ParseStream) -> bool {
         input.peek(Bracket)
     }
+
+    /// Applies the body of the repeat `count` times, calling `bind` with the
+    /// index of each iteration before it and `unbind` after it.
+    fn apply_iterations(
+        state: &mut EntryState,
+        count: usize,
+        body: &dyn StateOperation,
+        mut bind: impl FnMut(&mut EntryState, usize),
+        mut unbind: impl FnMut(&mut EntryState),
+    ) -> syn::Result<()> {
+        // Each iteration defines its own instances of the labels in the
+        // contents, within the scope of the whole repeat.
+        state.enter_scope();
+        for iteration in 0..count {
+            state.enter_iteration_scope(iteration);
+            bind(state, iteration);
+            body.apply_to(state)?;
+            unbind(state);
+            state.exit_scope();
+        }
+        state.exit_scope();
+        state.report_element_count(count);
+        Ok(())
+    }
+}
+
+impl Parse for RepeatEntry {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let content;
+        syn::bracketed!(content in input);
+        let repeat = if content.peek(syn::Token![for]) {
+            content.parse::<syn::Token![for]>()?;
+            let var: Ident = content.parse()?;
+            content.parse::<syn::Token![in]>()?;
+            let range: RepeatRange = content.parse()?;
+            let body: BlockEntry = content.parse()?;
+            Repeat::Range {
+                var,
+                range,
+                body: Box::new(Entry::BlockEntry(body)),
+            }
+        } else if content.peek(Ident) && content.peek2(syn::Token![in]) {
+            let var: Ident = content.parse()?;
+            content.parse::<syn::Token![in]>()?;
+            let range: RepeatRange = content.parse()?;
+            content.parse::<syn::Token![=>]>()?;
+            let body: Entry = content.parse()?;
+            Repeat::Range {
+                var,
+                range,
+                body: Box::new(body),
+            }
+        } else {
+            let contents: Contents = content.parse()?;
+            content.parse::<syn::Token![;]>()?;
+            if content.peek(syn::Token![#]) {
+                return Err(content.error(
+                    "Repeat counts cannot be interpolated, as the macro must know the size of the data when it expands, before interpolated values are computed; write the count as a constant expression of literals and range variables instead",
+                ));
+            }
+            let count: Expr = content.parse()?;
+            Repeat::Count { contents, count }
+        };
+        if !content.is_empty() {
+            return Err(content.error("unexpected tokens after repeat"));
+        }
+        Ok(Self { repeat })
+    }
 }
 
 impl StateOperation for RepeatEntry {
     fn apply_to(&self, state: &mut crate::state::EntryState) -> syn::Result<()> {
-        if !self.count.suffix().is_empty() {
-            return Err(syn::Error::new_spanned(
-                &self.count,
-                "suffixes are not allowed in repeat counts",
-            ));
+        match &self.repeat {
+            Repeat::Count { contents, count } => {
+                let value = count.eval_const(state)?;
+                let count = value.to_usize().ok_or_else(|| {
+                    syn::Error::new_spanned(
+                        count,
+                        format!("Repeat count must be a non-negative integer, not {value}"),
+                    )
+                })?;
+                Self::apply_iterations(state, count, contents, |_, _| {}, |_| {})
+            }
+            Repeat::Range { var, range, body } => {
+                let (start, count) = range.resolve(state)?;
+                let name = var.to_string();
+                Self::apply_iterations(
+                    state,
+                    count,
+                    body.as_ref(),
+                    |state, iteration| state.push_binding(name.clone(), &start + iteration),
+                    EntryState::pop_binding,
+                )
+            }
         }
-        let count: usize = self.count.base10_parse()?;
-        state.freeze_label_context();
-        for _ in 0..count {
-            self.contents.apply_to(state)?;
-        }
-        state.unfreeze_label_context();
-        Ok(())
     }
 }
@@ -10,6 +10,17 @@ pub struct SequenceEntry {
     entries: Punctuated<Entry, syn::Token![,]>,
 }
 
+impl SequenceEntry {
+    /// The number of elements in the sequence, as returned by `count`. Mode
+    /// changes are not elements, as they append nothing.
+    pub fn num_elements(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| !matches!(entry, Entry::ModeChange(_)))
+            .count()
+    }
+}
+
 impl StateOperation for SequenceEntry {
     fn apply_to(&self, state: &mut EntryState) -> syn::Result<()> {
         for entry in &self.entries {
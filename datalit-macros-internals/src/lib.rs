@@ -6,35 +6,179 @@ use proc_macro2::{Span, TokenStream};
 use crate::state::StateOperation as _;
 use quote::quote;
 
+mod checksum;
 mod entry;
 mod parse;
 mod state;
+mod template;
 mod to_bytes;
 
+/// Computes the data described by `input`.
+///
+/// Only data fully known to the macro can be returned, so interpolated values
+/// and `@align_output` are errors.
 pub fn generate_data(input: TokenStream) -> syn::Result<Vec<u8>> {
+    let input = match template::expand(input)? {
+        template::Expansion::Expanded(input) => input,
+        template::Expansion::Deferred(call) => {
+            return Err(syn::Error::new_spanned(
+                call,
+                "Templates are only available through the `datalit!` macro",
+            ));
+        }
+    };
+    let generated = generate(input)?;
+    if let Some(interpolation) = generated.interpolations.first() {
+        return Err(syn::Error::new_spanned(
+            interpolation.bytes_expr(),
+            "Interpolated values are computed by the generated code, so they are not supported here",
+        ));
+    }
+    if let Some((_, source)) = &generated.output_alignment {
+        return Err(syn::Error::new_spanned(
+            source,
+            "`@align_output` applies to the generated code, so it is not supported here",
+        ));
+    }
+    Ok(generated.data)
+}
+
+fn generate(input: TokenStream) -> syn::Result<state::Generated> {
     let entries: entry::SequenceEntry = syn::parse2(input)?;
 
-    let mut state = state::EntryState::new();
-    entries.apply_to(&mut state)?;
-    state.check()?;
-    state.generate_data()
+    // Variable-size fields start out as small as possible, and only ever grow,
+    // so this converges after at most a few passes.
+    let mut var_field_sizes = state::support::VarFieldSizes::new();
+    loop {
+        let mut state = state::EntryState::new(var_field_sizes);
+        entries.apply_to(&mut state)?;
+        state.check()?;
+        match state.generate_data()? {
+            state::GeneratedData::Complete(generated) => return Ok(generated),
+            state::GeneratedData::NeedsRelayout(sizes) => var_field_sizes = sizes,
+        }
+    }
 }
 
-pub fn generate_expr(input: TokenStream) -> syn::Result<TokenStream> {
-    let byte_array = generate_data(input)?
+/// Generates a `const __DATA: [u8; N]` item holding the data.
+///
+/// Interpolated values are written into the array when the const item is
+/// evaluated, so the data stays usable in const contexts.
+fn generate_data_const(generated: state::Generated) -> TokenStream {
+    let len = generated.data.len();
+    let byte_array = generated
+        .data
         .into_iter()
         .map(|b| syn::LitByte::new(b, Span::call_site()));
+    // Referencing included files with `include_bytes!` makes the compiler
+    // rebuild the invocation when they change.
+    let included_files = generated.included_files.iter();
+    let includes = quote! {
+        #(const _: &[u8] = include_bytes!(#included_files);)*
+    };
+
+    if generated.interpolations.is_empty() {
+        return quote! {
+            #includes
+            const __DATA: [u8; #len] = [
+                #(#byte_array),*
+            ];
+        };
+    }
+
+    let patches = generated.interpolations.iter().map(|interpolation| {
+        let offset = interpolation.field().start();
+        let size = interpolation.field().size();
+        let bytes_expr = interpolation.bytes_expr();
+        quote! {{
+            let __bytes: [u8; #size] = #bytes_expr;
+            let mut __i = 0;
+            while __i < #size {
+                __data[#offset + __i] = __bytes[__i];
+                __i += 1;
+            }
+        }}
+    });
+    quote! {
+        #includes
+        const __DATA: [u8; #len] = {
+            let mut __data: [u8; #len] = [
+                #(#byte_array),*
+            ];
+            #(#patches)*
+            __data
+        };
+    }
+}
+
+/// Generates an expression of type `&'static [u8]`.
+pub fn generate_expr(input: TokenStream) -> syn::Result<TokenStream> {
+    let input = match template::expand(input)? {
+        template::Expansion::Expanded(input) => input,
+        template::Expansion::Deferred(call) => return Ok(call),
+    };
+    let mut generated = generate(input)?;
+    let output_alignment = generated.output_alignment.take();
+    let data_const = generate_data_const(generated);
+    if let Some((alignment, _)) = output_alignment {
+        // The slice borrows from a static wrapper with the requested
+        // alignment.
+        let alignment = proc_macro2::Literal::usize_unsuffixed(alignment);
+        return Ok(quote! {{
+            #data_const
+            #[repr(C, align(#alignment))]
+            struct __Aligned([u8; __DATA.len()]);
+            static __ALIGNED: __Aligned = __Aligned(__DATA);
+            let __slice: &'static [u8] = &__ALIGNED.0;
+            __slice
+        }});
+    }
     Ok(quote! {{
-        let __slice: &'static [u8] = &[
-            #(#byte_array),*
-        ];
+        #data_const
+        let __slice: &'static [u8] = &__DATA;
         __slice
     }})
 }
 
+/// Generates an expression of type `[u8; N]`.
+pub fn generate_array_expr(input: TokenStream) -> syn::Result<TokenStream> {
+    let input = match template::expand(input)? {
+        template::Expansion::Expanded(input) => input,
+        template::Expansion::Deferred(call) => return Ok(call),
+    };
+    let generated = generate(input)?;
+    if let Some((_, source)) = &generated.output_alignment {
+        return Err(syn::Error::new_spanned(
+            source,
+            "`@align_output` is not supported by `datalit_array!`; wrap the array in an aligned type instead",
+        ));
+    }
+    let data_const = generate_data_const(generated);
+    Ok(quote! {{
+        #data_const
+        __DATA
+    }})
+}
+
+fn into_compile_errors(e: syn::Error) -> TokenStream {
+    let errors = e.into_iter().map(syn::Error::into_compile_error);
+    quote! {{#(#errors);*}}
+}
+
 pub fn generate_expr_raw(input: TokenStream) -> TokenStream {
-    generate_expr(input).unwrap_or_else(|e| {
-        let errors = e.into_iter().map(syn::Error::into_compile_error);
-        quote! {{#(#errors);*}}
-    })
+    generate_expr(input).unwrap_or_else(into_compile_errors)
+}
+
+pub fn generate_array_expr_raw(input: TokenStream) -> TokenStream {
+    generate_array_expr(input).unwrap_or_else(into_compile_errors)
+}
+
+/// Generates the `macro_rules!` macros defining templates.
+pub fn generate_template(input: TokenStream) -> syn::Result<TokenStream> {
+    let defs: template::TemplateDefs = syn::parse2(input)?;
+    Ok(defs.to_macro_rules())
+}
+
+pub fn generate_template_raw(input: TokenStream) -> TokenStream {
+    generate_template(input).unwrap_or_else(|e| e.to_compile_error())
 }
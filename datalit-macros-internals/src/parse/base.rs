@@ -2,32 +2,113 @@
 //!
 //! These can be used as members of other structures to build up more complex parsing.
 
+use num::ToPrimitive as _;
 use proc_macro2::TokenStream;
+use quote::ToTokens as _;
 use syn::{Ident, parse::ParseStream};
 
-use crate::to_bytes::{Endianness, IntType};
+use crate::to_bytes::{
+    BitOrder, Endianness, IntType, IntTypeExt as _, VarIntType, VarIntTypeExt as _,
+};
 
-#[derive(Debug, Clone)]
-pub struct PrimitiveSpec {
-    ident: Ident,
-    int_type: IntType,
-    endianness: Option<Endianness>,
+/// A primitive written into a fixed number of bytes, reserved when it is
+/// laid out.
+#[derive(Debug, Clone, Copy)]
+pub enum FixedPrimitive {
+    Int {
+        int_type: IntType,
+        endianness: Option<Endianness>,
+    },
+    /// A field of a `bits { ... }` group, `offset` bits from its start.
+    Bits {
+        order: BitOrder,
+        offset: usize,
+        width: usize,
+    },
 }
 
-impl PrimitiveSpec {
-    pub fn int_type(&self) -> IntType {
-        self.int_type
+impl FixedPrimitive {
+    /// A field of a bit group, written over the bits already in the group.
+    pub fn bit_field(order: BitOrder, offset: usize, width: usize) -> Self {
+        FixedPrimitive::Bits {
+            order,
+            offset,
+            width,
+        }
+    }
+
+    /// Returns the number of bytes the value is written into.
+    pub fn size(&self) -> usize {
+        match *self {
+            FixedPrimitive::Int { int_type, .. } => int_type.num_bytes(),
+            FixedPrimitive::Bits { offset, width, .. } => (offset + width).div_ceil(8),
+        }
     }
 
+    /// Writes `n` at the start of `buffer`. Bit fields only replace their own
+    /// bits, leaving the rest of the bytes they share as they were.
     pub fn write_int(
         &self,
         default_endianness: Endianness,
         n: &num::BigInt,
         buffer: &mut [u8],
     ) -> syn::Result<()> {
-        let int_type = self.int_type();
-        let endianness = self.endianness.unwrap_or(default_endianness);
-        int_type.write_bytes_from_bigint(n, endianness, buffer)
+        match *self {
+            FixedPrimitive::Int {
+                int_type,
+                endianness,
+            } => {
+                let endianness = endianness.unwrap_or(default_endianness);
+                int_type.write_bytes_from_bigint(n, endianness, buffer)
+            }
+            FixedPrimitive::Bits {
+                order,
+                offset,
+                width,
+            } => {
+                let max = BitOrder::max_value(width);
+                let value = n.to_u128().filter(|&value| value <= max).ok_or_else(|| {
+                    syn::Error::new(
+                        proc_macro2::Span::call_site(),
+                        format!(
+                            "Value {n} out of range for a {width}-bit field (must be 0..={max})"
+                        ),
+                    )
+                })?;
+                order.write_bits(buffer, offset, width, value);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A variable-length integer, whose size depends on its value.
+#[derive(Debug, Clone, Copy)]
+pub struct VarPrimitive(VarIntType);
+
+impl VarPrimitive {
+    /// Encodes `n`, padded to at least `min_len` bytes.
+    pub fn encode(&self, n: &num::BigInt, min_len: usize) -> syn::Result<Vec<u8>> {
+        self.0.bigint_to_bytes(n, min_len)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum PrimitiveType {
+    Fixed(FixedPrimitive),
+    Var(VarPrimitive),
+}
+
+/// The type an expression is written as, such as `u32_be` or `uleb`.
+#[derive(Debug, Clone)]
+pub struct PrimitiveSpec {
+    tokens: TokenStream,
+    prim_type: PrimitiveType,
+}
+
+impl PrimitiveSpec {
+    pub fn prim_type(&self) -> PrimitiveType {
+        self.prim_type
     }
 }
 
@@ -39,23 +120,36 @@ impl syn::parse::Parse for PrimitiveSpec {
 
         let endianness = Endianness::parse_from_suffix(&mut suffix);
 
-        let int_type = IntType::from_suffix(suffix).ok_or_else(|| {
-            syn::Error::new_spanned(
-                &ident,
-                format!("Invalid or missing integer type suffix: '{}'", ident),
-            )
-        })?;
+        let prim_type = if let Some(var_int_type) = VarIntType::from_suffix(suffix) {
+            if endianness.is_some() {
+                return Err(syn::Error::new_spanned(
+                    &ident,
+                    format!("Variable-length type '{var_int_type}' cannot have an endianness"),
+                ));
+            }
+            PrimitiveType::Var(VarPrimitive(var_int_type))
+        } else {
+            let int_type = IntType::from_suffix(suffix).ok_or_else(|| {
+                syn::Error::new_spanned(
+                    &ident,
+                    format!("Invalid or missing integer type suffix: '{}'", ident),
+                )
+            })?;
+            PrimitiveType::Fixed(FixedPrimitive::Int {
+                int_type,
+                endianness,
+            })
+        };
 
         Ok(PrimitiveSpec {
-            ident,
-            int_type,
-            endianness,
+            tokens: ident.to_token_stream(),
+            prim_type,
         })
     }
 }
 
 impl quote::ToTokens for PrimitiveSpec {
     fn to_tokens(&self, tokens: &mut TokenStream) {
-        self.ident.to_tokens(tokens);
+        self.tokens.to_tokens(tokens);
     }
 }
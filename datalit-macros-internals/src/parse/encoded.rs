@@ -0,0 +1,172 @@
+//! Byte strings written as text, such as `hex("de ad be ef")` or
+//! `base64("3q2+7w==")`.
+
+use std::ops::Range;
+
+use proc_macro2::Span;
+use syn::{Error, LitStr};
+
+/// A text encoding of binary data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteEncoding {
+    /// Pairs of hex digits, optionally separated by whitespace or colons.
+    Hex,
+    /// Base64 with the standard alphabet (`+` and `/`).
+    Base64,
+    /// Base64 with the URL-safe alphabet (`-` and `_`).
+    Base64Url,
+}
+
+impl ByteEncoding {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "hex" => Some(ByteEncoding::Hex),
+            "base64" => Some(ByteEncoding::Base64),
+            "base64url" => Some(ByteEncoding::Base64Url),
+            _ => None,
+        }
+    }
+
+    /// Decodes the value of `lit`. Errors point at the offending character
+    /// where the compiler supports it, and describe its position otherwise.
+    pub fn decode(self, lit: &LitStr) -> syn::Result<Vec<u8>> {
+        match self {
+            ByteEncoding::Hex => decode_hex(lit),
+            ByteEncoding::Base64 => decode_base64(lit, false),
+            ByteEncoding::Base64Url => decode_base64(lit, true),
+        }
+    }
+}
+
+fn decode_hex(lit: &LitStr) -> syn::Result<Vec<u8>> {
+    let value = lit.value();
+    let mut bytes = Vec::new();
+    // The high digit of the byte being decoded, and its index.
+    let mut high_digit: Option<(usize, u8)> = None;
+    for (i, c) in value.char_indices() {
+        if c.is_whitespace() || c == ':' {
+            continue;
+        }
+        let Some(digit) = c.to_digit(16) else {
+            return Err(char_error(lit, i, c, "Invalid hex digit", ""));
+        };
+        let digit = digit as u8;
+        match high_digit.take() {
+            Some((_, high)) => bytes.push(high << 4 | digit),
+            None => high_digit = Some((i, digit)),
+        }
+    }
+    if let Some((i, _)) = high_digit {
+        return Err(Error::new(
+            value_span(lit, i..i + 1),
+            format!(
+                "Hex string has an odd number of digits; the digit {} has no pair",
+                position(&value, i)
+            ),
+        ));
+    }
+    Ok(bytes)
+}
+
+fn base64_digit(c: char, url_safe: bool) -> Option<u32> {
+    let value = match c {
+        'A'..='Z' => c as u32 - 'A' as u32,
+        'a'..='z' => c as u32 - 'a' as u32 + 26,
+        '0'..='9' => c as u32 - '0' as u32 + 52,
+        '+' if !url_safe => 62,
+        '/' if !url_safe => 63,
+        '-' if url_safe => 62,
+        '_' if url_safe => 63,
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn decode_base64(lit: &LitStr, url_safe: bool) -> syn::Result<Vec<u8>> {
+    let value = lit.value();
+    let mut bytes = Vec::new();
+    let mut bits = 0u32;
+    let mut num_bits = 0;
+    let mut num_digits = 0usize;
+    // The index of the first `=`, and the number of them.
+    let mut padding: Option<(usize, usize)> = None;
+    for (i, c) in value.char_indices() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if c == '=' {
+            padding.get_or_insert((i, 0)).1 += 1;
+            continue;
+        }
+        if padding.is_some() {
+            return Err(char_error(lit, i, c, "Base64 digit", " after padding"));
+        }
+        let Some(digit) = base64_digit(c, url_safe) else {
+            let hint = match c {
+                '-' | '_' if !url_safe => " (use `base64url(...)` for the URL-safe alphabet)",
+                '+' | '/' if url_safe => " (use `base64(...)` for the standard alphabet)",
+                _ => "",
+            };
+            return Err(char_error(lit, i, c, "Invalid base64 digit", hint));
+        };
+        bits = bits << 6 | digit;
+        num_bits += 6;
+        num_digits += 1;
+        if num_bits >= 8 {
+            num_bits -= 8;
+            bytes.push((bits >> num_bits) as u8);
+            bits &= (1 << num_bits) - 1;
+        }
+    }
+    if num_digits % 4 == 1 {
+        return Err(Error::new_spanned(
+            lit,
+            format!("Base64 data has {num_digits} digits, which cannot form whole bytes"),
+        ));
+    }
+    if let Some((i, count)) = padding
+        && (count > 2 || !(num_digits + count).is_multiple_of(4))
+    {
+        return Err(Error::new(
+            value_span(lit, i..i + count),
+            format!("Incorrect base64 padding {}", position(&value, i)),
+        ));
+    }
+    Ok(bytes)
+}
+
+fn char_error(lit: &LitStr, i: usize, c: char, desc: &str, hint: &str) -> Error {
+    Error::new(
+        value_span(lit, i..i + c.len_utf8()),
+        format!("{desc} {c:?} {}{hint}", position(&lit.value(), i)),
+    )
+}
+
+/// Describes the position of the character at byte index `i` of `value`, for
+/// compilers that cannot point within the literal. Multi-line values, such as
+/// pasted blobs, are described by line and column.
+fn position(value: &str, i: usize) -> String {
+    let before = &value[..i];
+    if !value.contains('\n') {
+        return format!("at column {}", before.chars().count() + 1);
+    }
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    let column = value[line_start..i].chars().count() + 1;
+    format!("at line {line}, column {column}")
+}
+
+/// The span of `range` within the value of `lit`, or of the whole literal if
+/// the compiler cannot point within literals, or the literal has escapes.
+fn value_span(lit: &LitStr, range: Range<usize>) -> Span {
+    let token = lit.token();
+    let source = token.to_string();
+    let value = lit.value();
+    let offset = source
+        .find('"')
+        .map(|quote| quote + 1)
+        .filter(|&start| source[start..].starts_with(&value));
+    offset
+        .and_then(|offset| token.subspan(offset + range.start..offset + range.end))
+        .unwrap_or_else(|| lit.span())
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+
+use datalit_runtime::layout;
+use proc_macro2::TokenStream;
+use quote::ToTokens;
+use syn::{
+    Ident, Lifetime, LitInt,
+    parse::{Parse, ParseStream},
+    token::Bracket,
+};
+
+/// A reference to a label, optionally qualified by the label of the block it
+/// is defined in, e.g. `'data` or `'chunk1::data`. Each part may select the
+/// instance defined in one iteration of a repeat, e.g. `'data[2]`.
+#[derive(Clone)]
+pub struct LabelPath {
+    root: Lifetime,
+    root_index: Option<LabelIndex>,
+    segments: Vec<LabelSegment>,
+}
+
+/// A label looked up within the block of the previous label of a path.
+#[derive(Clone)]
+pub struct LabelSegment {
+    name: Ident,
+    index: Option<LabelIndex>,
+}
+
+impl LabelSegment {
+    /// The iteration of the repeat defining the label, if selected.
+    pub fn index(&self) -> Option<usize> {
+        self.index.as_ref().map(|index| index.value)
+    }
+}
+
+/// The iteration selected by a label path part, e.g. the `[2]` in `'data[2]`.
+#[derive(Clone)]
+struct LabelIndex {
+    bracket: Bracket,
+    lit: LitInt,
+    value: usize,
+}
+
+impl LabelIndex {
+    fn parse_opt(input: ParseStream) -> syn::Result<Option<Self>> {
+        if !input.peek(Bracket) {
+            return Ok(None);
+        }
+        let content;
+        let bracket = syn::bracketed!(content in input);
+        let lit: LitInt = content.parse()?;
+        if !lit.suffix().is_empty() {
+            return Err(syn::Error::new_spanned(
+                &lit,
+                "suffixes are not allowed in label indices",
+            ));
+        }
+        let value = lit.base10_parse()?;
+        Ok(Some(Self {
+            bracket,
+            lit,
+            value,
+        }))
+    }
+}
+
+impl LabelPath {
+    /// The iteration of the repeat defining the first label, if selected.
+    pub fn root_index(&self) -> Option<usize> {
+        self.root_index.as_ref().map(|index| index.value)
+    }
+
+    /// This path, as resolved by the layout engine.
+    pub fn to_layout_path(&self) -> layout::LabelPath {
+        let mut path = layout::LabelPath::new(self.root.ident.to_string(), self.root_index());
+        for segment in &self.segments {
+            path.push(segment.name.to_string(), segment.index());
+        }
+        path
+    }
+}
+
+impl Parse for LabelPath {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let root = input.parse()?;
+        let root_index = LabelIndex::parse_opt(input)?;
+        let mut segments = Vec::new();
+        while input.peek(syn::Token![::]) {
+            input.parse::<syn::Token![::]>()?;
+            segments.push(LabelSegment {
+                name: input.parse()?,
+                index: LabelIndex::parse_opt(input)?,
+            });
+        }
+        Ok(Self {
+            root,
+            root_index,
+            segments,
+        })
+    }
+}
+
+impl ToTokens for LabelIndex {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        self.bracket
+            .surround(tokens, |tokens| self.lit.to_tokens(tokens));
+    }
+}
+
+impl ToTokens for LabelPath {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        self.root.to_tokens(tokens);
+        self.root_index.to_tokens(tokens);
+        for segment in &self.segments {
+            syn::Token![::](segment.name.span()).to_tokens(tokens);
+            segment.name.to_tokens(tokens);
+            segment.index.to_tokens(tokens);
+        }
+    }
+}
+
+impl fmt::Display for LabelIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}]", self.value)
+    }
+}
+
+impl fmt::Display for LabelPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.root.ident)?;
+        if let Some(index) = &self.root_index {
+            write!(f, "{index}")?;
+        }
+        for segment in &self.segments {
+            write!(f, "::{}", segment.name)?;
+            if let Some(index) = &segment.index {
+                write!(f, "{index}")?;
+            }
+        }
+        Ok(())
+    }
+}
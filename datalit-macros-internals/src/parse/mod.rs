@@ -1,3 +1,5 @@
 //! Parsing utilities and structures for datalit macros.
 
 pub mod base;
+pub mod encoded;
+pub mod label_path;
@@ -1,85 +1,214 @@
 pub mod support;
 
-use std::collections::{BTreeMap, btree_map::Entry};
+use std::collections::BTreeSet;
 
+use datalit_runtime::layout::{LabelError, LabelStart, Layout};
+use proc_macro2::TokenStream;
+use quote::ToTokens as _;
 use syn::Lifetime;
 
 use crate::{
-    state::support::{LocationMap, PatchOp},
-    to_bytes::Endianness,
+    parse::label_path::LabelPath,
+    state::support::{DataRange, Interpolation, LabelRef, LocationMap, VarFieldSizes},
+    to_bytes::{Endianness, StringEncoding},
 };
 
-struct LabelInfo {
-    source_token: Lifetime,
+/// The final output of a laid out [`EntryState`].
+pub struct Generated {
+    pub data: Vec<u8>,
+    /// Files whose contents were included in the data.
+    pub included_files: BTreeSet<String>,
+    /// Values written into `data` by the generated code.
+    pub interpolations: Vec<Interpolation>,
+    /// The alignment requested with `@align_output`, and the mode change that
+    /// requested it.
+    pub output_alignment: Option<(usize, TokenStream)>,
 }
 
-struct LabelRef {
-    source_token: Lifetime,
+/// The result of generating data from a laid out [`EntryState`].
+pub enum GeneratedData {
+    /// The final data.
+    Complete(Generated),
+    /// Some variable-size fields did not fit in the space reserved for them.
+    /// The entries must be laid out again with the given sizes.
+    NeedsRelayout(VarFieldSizes),
 }
 
 pub struct EntryState {
-    data: Vec<u8>,
-    patch_ops: Vec<PatchOp>,
-    location_map: LocationMap,
-    defined_labels: BTreeMap<String, LabelInfo>,
-    used_labels: BTreeMap<String, LabelRef>,
+    /// The data and its labels and patches. Labels, label uses and data
+    /// patches are identified by their tokens in errors.
+    layout: Layout<syn::Error, TokenStream>,
     endian_mode: Endianness,
-    num_frozen_label_contexts: usize,
+    fill_byte: u8,
+    string_encoding: StringEncoding,
+    included_files: BTreeSet<String>,
+    interpolations: Vec<Interpolation>,
+    output_alignment: Option<(usize, TokenStream)>,
+    /// The variables bound by the enclosing repeats, innermost last.
+    bindings: Vec<(String, num::BigInt)>,
 }
 
 impl EntryState {
-    pub fn new() -> Self {
+    pub fn new(var_field_sizes: VarFieldSizes) -> Self {
         Self {
-            data: Vec::new(),
-            patch_ops: Vec::new(),
-            location_map: LocationMap::new(),
-            defined_labels: BTreeMap::new(),
-            used_labels: BTreeMap::new(),
+            layout: Layout::new(var_field_sizes),
             endian_mode: Endianness::Native,
-            num_frozen_label_contexts: 0,
+            fill_byte: 0x00,
+            string_encoding: StringEncoding::UTF8,
+            included_files: BTreeSet::new(),
+            interpolations: Vec::new(),
+            output_alignment: None,
+            bindings: Vec::new(),
         }
     }
 
-    pub fn report_label_def(
-        &mut self,
-        label: &Lifetime,
-        start: usize,
-        end: usize,
-    ) -> syn::Result<()> {
-        if self.num_frozen_label_contexts > 0 {
-            return Err(syn::Error::new_spanned(
-                label,
-                "Cannot define labels within a frozen label context",
-            ));
-        }
-        let label_str = label.ident.to_string();
-        match self.defined_labels.entry(label_str) {
-            Entry::Vacant(vacant) => {
-                vacant.insert(LabelInfo {
-                    source_token: label.clone(),
-                });
-                self.location_map
-                    .insert(label.ident.to_string(), support::DataRange::new(start, end));
-            }
-            Entry::Occupied(occ) => {
+    /// Records that the data depends on the contents of the file at `path`.
+    pub fn report_included_file(&mut self, path: &str) {
+        self.included_files.insert(path.to_owned());
+    }
+
+    /// Starts a scope for the labels defined within a block.
+    pub fn enter_scope(&mut self) {
+        self.layout.enter_scope();
+    }
+
+    /// Starts a scope for the labels defined within one iteration of a
+    /// repeat, which can be selected by its index, as in `'label[2]`.
+    pub fn enter_iteration_scope(&mut self, iteration: usize) {
+        self.layout.enter_iteration_scope(iteration);
+    }
+
+    /// Returns true if entries are being applied outside any block or repeat.
+    pub fn at_top_level(&self) -> bool {
+        self.layout.at_top_level()
+    }
+
+    pub fn exit_scope(&mut self) {
+        self.layout.exit_scope();
+    }
+
+    /// Defines `label` for an entry starting at the current offset. Its end
+    /// is set by [`report_label_def`](Self::report_label_def) once the entry
+    /// has been applied.
+    pub fn begin_label_def(&mut self, label: &Lifetime) -> syn::Result<LabelStart> {
+        self.layout
+            .begin_label_def(&label.ident.to_string(), label.to_token_stream())
+            .map_err(|original| {
                 let mut err1 = syn::Error::new_spanned(label, "Duplicate label");
                 err1.combine(syn::Error::new_spanned(
-                    &occ.get().source_token,
+                    self.layout.label_source(original),
                     "Originally defined here",
                 ));
+                err1
+            })
+    }
+
+    pub fn report_label_def(&mut self, start: LabelStart) {
+        self.layout.report_label_def(start);
+    }
+
+    /// Records the number of elements of a block or repeat that has just been
+    /// applied, which is available through `count('label)` if it is labeled.
+    pub fn report_element_count(&mut self, count: usize) {
+        self.layout.report_element_count(count);
+    }
+
+    /// Discards the element count of a block or repeat applied within an
+    /// entry that is not itself one, so that labeling it gives no count.
+    pub fn clear_element_count(&mut self) {
+        self.layout.clear_element_count();
+    }
+
+    /// Records a use of a label from the current scope, to be resolved by
+    /// [`check`](Self::check).
+    pub fn report_label_use(&mut self, path: &LabelPath) -> LabelRef {
+        self.layout
+            .report_label_use(path.to_layout_path(), path.to_token_stream())
+    }
+
+    /// Returns the start of the label `path` refers to, for values needed
+    /// while laying out the data, such as the base of
+    /// `pad_to(0x40, from = 'sect)`.
+    ///
+    /// Only labels defined before this point, or labeling an enclosing entry,
+    /// are known. [`check`](Self::check) reports an error if the use refers
+    /// to another label once all of them are defined.
+    pub fn laid_out_label_start(&mut self, path: &LabelPath) -> syn::Result<usize> {
+        let source = path.to_token_stream();
+        let layout_path = path.to_layout_path();
+        self.layout
+            .laid_out_label_start(layout_path.clone(), source.clone())
+            .map_err(|err| match err {
+                LabelError::NotDefined => syn::Error::new_spanned(
+                    path,
+                    format!(
+                        "Label '{path}' is not laid out yet; only labels defined before this point, or enclosing it, can be used here"
+                    ),
+                ),
+                err => self.label_error(&layout_path, &source, err),
+            })
+    }
 
-                return Err(err1);
+    /// Describes why the label use `path`, written as `source`, could not be
+    /// resolved.
+    fn label_error(
+        &self,
+        path: &datalit_runtime::layout::LabelPath,
+        source: &TokenStream,
+        err: LabelError,
+    ) -> syn::Error {
+        match err {
+            LabelError::NotDefined => {
+                syn::Error::new_spanned(source, format!("Label '{path}' used but not defined"))
+            }
+            LabelError::Ambiguous(candidates) => {
+                let mut err = syn::Error::new_spanned(
+                    source,
+                    format!(
+                        "Label '{path}' is ambiguous; qualify it with the label of the block defining it, or select a repeat iteration (e.g. `'{}[0]`)",
+                        path.root().name
+                    ),
+                );
+                for candidate in candidates {
+                    err.combine(syn::Error::new_spanned(
+                        self.layout.label_source(candidate),
+                        "Candidate defined here",
+                    ));
+                }
+                err
             }
-        };
+            LabelError::NotABlock { label, segment } => syn::Error::new_spanned(
+                source,
+                format!("Label '{label}' does not label a block, so it has no label '{segment}'"),
+            ),
+            LabelError::DefinedLater => syn::Error::new_spanned(
+                source,
+                format!(
+                    "Label '{path}' refers to a label defined after this point, but its value is needed to lay out the data"
+                ),
+            ),
+        }
+    }
 
-        Ok(())
+    /// Binds a repeat variable for the entries applied until the matching
+    /// [`pop_binding`](Self::pop_binding).
+    pub fn push_binding(&mut self, name: String, value: num::BigInt) {
+        self.bindings.push((name, value));
+    }
+
+    pub fn pop_binding(&mut self) {
+        self.bindings
+            .pop()
+            .expect("Popped a binding that was never pushed");
     }
 
-    pub fn report_label_use(&mut self, label: &Lifetime) {
-        let label_str = label.ident.to_string();
-        self.used_labels.entry(label_str).or_insert(LabelRef {
-            source_token: label.clone(),
-        });
+    /// The value of the innermost variable named `name`.
+    pub fn binding(&self, name: &str) -> Option<&num::BigInt> {
+        self.bindings
+            .iter()
+            .rev()
+            .find(|(bound, _)| bound == name)
+            .map(|(_, value)| value)
     }
 
     pub fn endian_mode(&self) -> Endianness {
@@ -90,15 +219,56 @@ impl EntryState {
         self.endian_mode = mode;
     }
 
-    pub fn check(&self) -> syn::Result<()> {
-        let mut errors = Vec::new();
+    /// The byte used to fill padding, set with `@fill`.
+    pub fn fill_byte(&self) -> u8 {
+        self.fill_byte
+    }
 
-        for (label_str, label_info) in &self.used_labels {
-            if !self.defined_labels.contains_key(label_str) {
-                errors.push(syn::Error::new_spanned(
-                    &label_info.source_token,
-                    format!("Label '{label_str}' used but not defined"),
-                ));
+    pub fn set_fill_byte(&mut self, fill: u8) {
+        self.fill_byte = fill;
+    }
+
+    /// The encoding of plain string literals, set with `@string_encoding`.
+    pub fn string_encoding(&self) -> StringEncoding {
+        self.string_encoding
+    }
+
+    pub fn set_string_encoding(&mut self, encoding: StringEncoding) {
+        self.string_encoding = encoding;
+    }
+
+    /// Requests that the output be aligned to `alignment` bytes. `source` is
+    /// used to report errors.
+    pub fn set_output_alignment(
+        &mut self,
+        alignment: usize,
+        source: TokenStream,
+    ) -> syn::Result<()> {
+        if self.output_alignment.is_some() {
+            return Err(syn::Error::new_spanned(
+                source,
+                "Output alignment can only be set once",
+            ));
+        }
+        self.output_alignment = Some((alignment, source));
+        Ok(())
+    }
+
+    /// Resolves every label use, reporting those that are not defined.
+    pub fn check(&mut self) -> syn::Result<()> {
+        let unresolved: Vec<_> = self
+            .layout
+            .resolve_label_uses()
+            .into_iter()
+            .map(|(path, source, err)| (path.clone(), source.clone(), err))
+            .collect();
+        let mut errors = Vec::new();
+        let mut reported = BTreeSet::new();
+        for (path, source, err) in unresolved {
+            let err = self.label_error(&path, &source, err);
+            // Uses repeated within arrays are only reported once.
+            if reported.insert(err.to_string()) {
+                errors.push(err);
             }
         }
 
@@ -116,41 +286,113 @@ impl EntryState {
         }
     }
 
-    pub fn generate_data(mut self) -> syn::Result<Vec<u8>> {
-        // Apply all deferred patch operations
-        for patch_op in self.patch_ops.drain(..) {
-            patch_op.apply(&self.location_map, &mut self.data)?;
+    pub fn generate_data(mut self) -> syn::Result<GeneratedData> {
+        if let Some(sizes) = self.layout.apply_patches()? {
+            return Ok(GeneratedData::NeedsRelayout(sizes));
+        }
+
+        // Interpolated values are only known once the generated code is
+        // evaluated, long after data patches have been computed.
+        let location_map = self.layout.location_map();
+        let mut errors = self
+            .layout
+            .data_patch_ops()
+            .iter()
+            .filter(|op| {
+                op.read_ranges(location_map).any(|range| {
+                    self.interpolations
+                        .iter()
+                        .any(|interpolation| interpolation.field().overlaps(range))
+                })
+            })
+            .map(|op| {
+                syn::Error::new_spanned(
+                    op.source(),
+                    "Values computed from data cannot read interpolated values",
+                )
+            });
+        if let Some(mut combined) = errors.next() {
+            combined.extend(errors);
+            return Err(combined);
         }
-        Ok(self.data)
+
+        self.layout.apply_data_patches(|sources| {
+            let mut errors = sources.into_iter().map(|source| {
+                syn::Error::new_spanned(
+                    source,
+                    "Circular dependency between values computed from data",
+                )
+            });
+            let mut combined = errors.next().unwrap();
+            combined.extend(errors);
+            combined
+        })?;
+        Ok(GeneratedData::Complete(Generated {
+            data: self.layout.into_data(),
+            included_files: self.included_files,
+            interpolations: self.interpolations,
+            output_alignment: self.output_alignment,
+        }))
     }
 
     pub fn append_bytes(&mut self, bytes: &[u8]) {
-        self.data.extend_from_slice(bytes);
+        self.layout.append_bytes(bytes);
+    }
+
+    /// Appends `n` bytes of padding with the value `fill`.
+    pub fn pad_bytes(&mut self, n: usize, fill: u8) {
+        self.layout.pad_bytes(n, fill);
     }
 
+    /// Reserves `n` zeroed bytes, to be filled in by a patch.
     pub fn advance_bytes(&mut self, n: usize) {
-        let start = self.data.len();
-        self.data.resize(start + n, 0);
+        self.layout.advance_bytes(n);
     }
 
     pub fn curr_offset(&self) -> usize {
-        self.data.len()
+        self.layout.curr_offset()
     }
 
     pub fn defer_patch_op<F>(&mut self, f: F)
     where
         F: FnOnce(&LocationMap, &mut [u8]) -> syn::Result<()> + 'static,
     {
-        self.patch_ops.push(PatchOp::new(f));
+        self.layout.defer_patch_op(f);
+    }
+
+    /// Reserves space at the current offset for a field whose size depends on
+    /// its value, and defers computing its encoding until all labels are known.
+    pub fn defer_var_patch_op<F>(&mut self, f: F)
+    where
+        F: FnOnce(&LocationMap, usize) -> syn::Result<Vec<u8>> + 'static,
+    {
+        self.layout.defer_var_patch_op(f);
     }
 
-    pub fn freeze_label_context(&mut self) {
-        self.num_frozen_label_contexts += 1;
+    /// Defers a patch of `field` that reads the data within the `reads` labels.
+    /// The patch is applied after all other patches; `source` is used to
+    /// report dependency errors.
+    pub fn defer_data_patch_op<F>(
+        &mut self,
+        field: DataRange,
+        reads: Vec<LabelRef>,
+        source: TokenStream,
+        f: F,
+    ) where
+        F: FnOnce(&LocationMap, &mut [u8]) -> syn::Result<()> + 'static,
+    {
+        self.layout.defer_data_patch_op(field, reads, source, f);
     }
 
-    pub fn unfreeze_label_context(&mut self) {
-        assert!(self.num_frozen_label_contexts > 0);
-        self.num_frozen_label_contexts -= 1;
+    /// Reserves `size` bytes at the current offset for a value computed by
+    /// `bytes_expr` in the generated code.
+    pub fn defer_interpolation(&mut self, size: usize, bytes_expr: TokenStream) {
+        let offset = self.curr_offset();
+        self.advance_bytes(size);
+        self.interpolations.push(Interpolation::new(
+            DataRange::new(offset, offset + size),
+            bytes_expr,
+        ));
     }
 }
 
@@ -1,75 +1,34 @@
 //! Contains types that need to be available for the macro, but are not part of its public API.
 
-use std::collections::BTreeMap;
-
-#[derive(Clone, Copy, Debug)]
-pub struct DataRange {
-    start: usize,
-    end: usize,
+use proc_macro2::TokenStream;
+
+pub use datalit_runtime::layout::{DataRange, LabelRef, LocationMap, VarFieldSizes};
+
+/// A value computed by a Rust expression in the generated code, such as
+/// `#(MAGIC): u32_le`.
+///
+/// The field holds zeroes in the data computed by the macro, and is filled in
+/// when the generated code is const-evaluated.
+pub struct Interpolation {
+    field: DataRange,
+    bytes_expr: TokenStream,
 }
 
-impl DataRange {
-    #[must_use]
-    pub fn new(start: usize, end: usize) -> Self {
-        assert!(start <= end);
-        Self { start, end }
-    }
-
+impl Interpolation {
+    /// `bytes_expr` must evaluate to a `[u8; N]` where `N` is the size of
+    /// `field`.
     #[must_use]
-    pub fn start(&self) -> usize {
-        self.start
+    pub fn new(field: DataRange, bytes_expr: TokenStream) -> Self {
+        Self { field, bytes_expr }
     }
 
     #[must_use]
-    pub fn end(&self) -> usize {
-        self.end
+    pub fn field(&self) -> DataRange {
+        self.field
     }
 
     #[must_use]
-    pub fn size(&self) -> usize {
-        self.end - self.start
-    }
-}
-
-pub struct LocationMap(BTreeMap<String, DataRange>);
-
-impl LocationMap {
-    #[must_use]
-    pub fn new() -> Self {
-        Self(BTreeMap::new())
-    }
-
-    pub fn insert(&mut self, label: String, range: DataRange) {
-        let had_value = self.0.insert(label, range).is_some();
-        assert!(!had_value, "Duplicate label inserted into LocationMap");
-    }
-
-    #[must_use]
-    pub fn get(&self, label: &str) -> Option<DataRange> {
-        self.0.get(label).copied()
-    }
-}
-
-impl Default for LocationMap {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-type RawPatchOp = Box<dyn FnOnce(&LocationMap, &mut [u8]) -> syn::Result<()>>;
-
-pub struct PatchOp(RawPatchOp);
-
-impl PatchOp {
-    #[must_use]
-    pub fn new<F>(f: F) -> Self
-    where
-        F: FnOnce(&LocationMap, &mut [u8]) -> syn::Result<()> + 'static,
-    {
-        Self(Box::new(f))
-    }
-
-    pub fn apply(self, location_map: &LocationMap, data: &mut [u8]) -> syn::Result<()> {
-        (self.0)(location_map, data)
+    pub fn bytes_expr(&self) -> &TokenStream {
+        &self.bytes_expr
     }
 }
@@ -0,0 +1,350 @@
+//! Templates: named fragments of entries with parameters, defined with
+//! `datalit_template!` and invoked as `name!(args...)` inside `datalit!`.
+//!
+//! A proc macro cannot see items defined elsewhere, so `datalit_template!`
+//! defines a `macro_rules!` macro carrying the template's definition. When
+//! `datalit!` finds a call to a template it has no definition for, it expands
+//! to a call of that macro, which invokes `datalit!` again with the definition
+//! prepended to the original input as `@__template name(params) = { ... }`.
+//! The path of the macro to invoke again is passed in by the `datalit` crate's
+//! `macro_rules!` wrappers as `$crate::datalit`, so it resolves however the
+//! crate is named.
+//! Once every definition is known, calls are replaced by blocks before the
+//! entries are parsed.
+
+use std::collections::BTreeMap;
+
+use proc_macro2::{Delimiter, Group, Spacing, TokenStream, TokenTree};
+use quote::quote;
+use syn::{
+    Attribute, Error, Ident, Token,
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+};
+
+/// How deeply templates may invoke other templates.
+const MAX_DEPTH: usize = 64;
+
+/// A template definition, e.g. `chunk(tag, body) = { tag, body }`.
+#[derive(derive_syn_parse::Parse)]
+pub struct TemplateDef {
+    #[call(Attribute::parse_outer)]
+    attrs: Vec<Attribute>,
+    name: Ident,
+    #[paren]
+    _params_paren: syn::token::Paren,
+    #[inside(_params_paren)]
+    #[call(Punctuated::parse_terminated)]
+    params: Punctuated<Ident, Token![,]>,
+    _eq: Token![=],
+    #[brace]
+    _body_brace: syn::token::Brace,
+    #[inside(_body_brace)]
+    body: TokenStream,
+}
+
+impl TemplateDef {
+    fn to_macro_rules(&self) -> TokenStream {
+        let TemplateDef {
+            attrs,
+            name,
+            params,
+            body,
+            ..
+        } = self;
+        let params = params.iter();
+        let message =
+            format!("`{name}!` is a datalit template, and can only be used inside `datalit!`");
+        quote! {
+            #(#attrs)*
+            macro_rules! #name {
+                (@__datalit_template { $($callback:tt)* } { $($input:tt)* }) => {
+                    $($callback)*! {
+                        @__template #name(#(#params),*) = { #body }
+                        $($input)*
+                    }
+                };
+                ($($args:tt)*) => {
+                    ::core::compile_error!(#message)
+                };
+            }
+        }
+    }
+}
+
+/// The input of `datalit_template!`: one or more definitions, separated by
+/// semicolons.
+pub struct TemplateDefs(Punctuated<TemplateDef, Token![;]>);
+
+impl Parse for TemplateDefs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let defs = Punctuated::parse_terminated(input)?;
+        if defs.is_empty() {
+            return Err(input.error("Expected a template definition"));
+        }
+        Ok(Self(defs))
+    }
+}
+
+impl TemplateDefs {
+    pub fn to_macro_rules(&self) -> TokenStream {
+        self.0.iter().map(TemplateDef::to_macro_rules).collect()
+    }
+}
+
+/// Macro input, with the template definitions added by template macros.
+struct TemplateInput {
+    defs: Vec<TemplateDef>,
+    entries: TokenStream,
+}
+
+impl Parse for TemplateInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut defs = Vec::new();
+        while input.peek(Token![@]) && input.peek2(Ident) {
+            let fork = input.fork();
+            fork.parse::<Token![@]>()?;
+            if fork.parse::<Ident>()? != "__template" {
+                break;
+            }
+            input.parse::<Token![@]>()?;
+            input.parse::<Ident>()?;
+            defs.push(input.parse()?);
+        }
+        Ok(Self {
+            defs,
+            entries: input.parse()?,
+        })
+    }
+}
+
+pub enum Expansion {
+    /// All template calls were replaced.
+    Expanded(TokenStream),
+    /// A template definition is needed; this is the call of its macro.
+    Deferred(TokenStream),
+}
+
+/// Splits the path of the macro to reinvoke with template definitions from
+/// the start of `input`, where the `datalit` crate's macros pass it as
+/// `@__datalit_callback { $crate::datalit }`.
+fn split_callback(input: TokenStream) -> (Option<TokenStream>, TokenStream) {
+    let tokens = input.clone().into_iter().collect::<Vec<_>>();
+    match tokens.as_slice() {
+        [
+            TokenTree::Punct(at),
+            TokenTree::Ident(name),
+            TokenTree::Group(callback),
+            rest @ ..,
+        ] if at.as_char() == '@'
+            && name == "__datalit_callback"
+            && callback.delimiter() == Delimiter::Brace =>
+        {
+            (Some(callback.stream()), rest.iter().cloned().collect())
+        }
+        _ => (None, input),
+    }
+}
+
+/// Replaces template calls in `input`. If a template is not yet known, returns
+/// a call of its macro that reinvokes the callback macro given in `input` with
+/// the definition added.
+pub fn expand(input: TokenStream) -> syn::Result<Expansion> {
+    let (callback, input) = split_callback(input);
+    let TemplateInput { defs, entries } = syn::parse2(input.clone())?;
+    let mut expander = Expander {
+        templates: defs.iter().map(|def| (def.name.to_string(), def)).collect(),
+        missing: None,
+    };
+    let expanded = expander.expand_tokens(entries, 0)?;
+    match (expander.missing, callback) {
+        (Some(name), Some(callback)) => Ok(Expansion::Deferred(quote! {
+            #name! { @__datalit_template { #callback } { #input } }
+        })),
+        (Some(name), None) => Err(Error::new_spanned(
+            name,
+            "Templates are only available through the `datalit` crate's macros",
+        )),
+        (None, _) => Ok(Expansion::Expanded(expanded)),
+    }
+}
+
+struct Expander<'a> {
+    templates: BTreeMap<String, &'a TemplateDef>,
+    missing: Option<Ident>,
+}
+
+impl Expander<'_> {
+    fn expand_tokens(&mut self, tokens: TokenStream, depth: usize) -> syn::Result<TokenStream> {
+        let mut output = TokenStream::new();
+        let mut tokens = tokens.into_iter().peekable();
+        while let Some(token) = tokens.next() {
+            match token {
+                // Interpolated values are Rust code, and left as they are.
+                TokenTree::Punct(punct) if punct.as_char() == '#' => {
+                    output.extend([TokenTree::Punct(punct)]);
+                    if let Some(TokenTree::Group(_)) = tokens.peek() {
+                        output.extend(tokens.next());
+                    }
+                }
+                TokenTree::Ident(name) if is_call(&tokens) => {
+                    let bang = tokens.next().expect("peeked");
+                    let Some(TokenTree::Group(args)) = tokens.next() else {
+                        unreachable!("peeked");
+                    };
+                    match self.templates.get(&name.to_string()).copied() {
+                        Some(def) => {
+                            let body = self.instantiate(def, &name, &args)?;
+                            if depth >= MAX_DEPTH {
+                                return Err(Error::new_spanned(
+                                    &name,
+                                    format!(
+                                        "Templates nested too deeply while expanding `{name}!`"
+                                    ),
+                                ));
+                            }
+                            let body = self.expand_tokens(body, depth + 1)?;
+                            let mut block = Group::new(Delimiter::Brace, body);
+                            block.set_span(args.span());
+                            output.extend([TokenTree::Group(block)]);
+                        }
+                        None => {
+                            self.missing.get_or_insert_with(|| name.clone());
+                            output.extend([TokenTree::Ident(name), bang, TokenTree::Group(args)]);
+                        }
+                    }
+                }
+                TokenTree::Group(group) => {
+                    let stream = self.expand_tokens(group.stream(), depth)?;
+                    let mut new_group = Group::new(group.delimiter(), stream);
+                    new_group.set_span(group.span());
+                    output.extend([TokenTree::Group(new_group)]);
+                }
+                token => output.extend([token]),
+            }
+        }
+        Ok(output)
+    }
+
+    /// Returns the body of `def` with its parameters replaced by the
+    /// arguments.
+    fn instantiate(
+        &self,
+        def: &TemplateDef,
+        name: &Ident,
+        args_group: &Group,
+    ) -> syn::Result<TokenStream> {
+        let args = split_args(args_group.stream());
+        if args.len() != def.params.len() {
+            return Err(Error::new(
+                args_group.span(),
+                format!(
+                    "Template `{name}!` takes {} argument(s), but {} were given",
+                    def.params.len(),
+                    args.len()
+                ),
+            ));
+        }
+        let args = def
+            .params
+            .iter()
+            .map(ToString::to_string)
+            .zip(args)
+            .collect::<BTreeMap<_, _>>();
+        Ok(substitute(def.body.clone(), &args))
+    }
+}
+
+/// Returns whether the tokens continue with `!(...)`, `![...]` or `!{...}`.
+fn is_call(tokens: &std::iter::Peekable<proc_macro2::token_stream::IntoIter>) -> bool {
+    let mut lookahead = tokens.clone();
+    matches!(lookahead.next(), Some(TokenTree::Punct(p)) if p.as_char() == '!')
+        && matches!(lookahead.next(), Some(TokenTree::Group(g)) if g.delimiter() != Delimiter::None)
+}
+
+fn split_args(args: TokenStream) -> Vec<TokenStream> {
+    let mut result = vec![TokenStream::new()];
+    for token in args {
+        match &token {
+            TokenTree::Punct(p) if p.as_char() == ',' => result.push(TokenStream::new()),
+            _ => result.last_mut().expect("non-empty").extend([token]),
+        }
+    }
+    // Allow a trailing comma, and no arguments at all.
+    if result.last().is_some_and(TokenStream::is_empty) {
+        result.pop();
+    }
+    result
+}
+
+/// Replaces the parameters in `tokens` with their arguments.
+///
+/// Only identifiers used as values are parameters, so a parameter may share
+/// its name with a function, directive, keyword argument, label or type, as in
+/// `rec(len, body) = { len, len('b): u8, 'b: body }`. Interpolated Rust code
+/// is left as it is.
+fn substitute(tokens: TokenStream, args: &BTreeMap<String, TokenStream>) -> TokenStream {
+    let tokens = tokens.into_iter().collect::<Vec<_>>();
+    let mut output = TokenStream::new();
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            // Labels are left as they are, even if named like a parameter.
+            TokenTree::Ident(_) if i > 0 && is_punct(&tokens[i - 1], '\'') => {
+                output.extend([token.clone()]);
+            }
+            TokenTree::Ident(ident) if is_value(&tokens, i) => match args.get(&ident.to_string()) {
+                Some(arg) => output.extend(arg.clone()),
+                None => output.extend([token.clone()]),
+            },
+            TokenTree::Group(group) if i > 0 && is_punct(&tokens[i - 1], '#') => {
+                output.extend([TokenTree::Group(group.clone())]);
+            }
+            TokenTree::Group(group) => {
+                let stream = substitute(group.stream(), args);
+                let mut new_group = Group::new(group.delimiter(), stream);
+                new_group.set_span(group.span());
+                output.extend([TokenTree::Group(new_group)]);
+            }
+            token => output.extend([token.clone()]),
+        }
+    }
+    output
+}
+
+fn is_punct(token: &TokenTree, ch: char) -> bool {
+    matches!(token, TokenTree::Punct(p) if p.as_char() == ch)
+}
+
+/// Returns whether the identifier at `tokens[i]` is used as a value, i.e. as
+/// an entry or an operand of an expression.
+///
+/// It is not if it names a call or block (`len(...)`, `bits { ... }`), a
+/// keyword argument or mode (`fill = ...`, `@endian`), a label path segment
+/// (`'chunk::data`) or a type (`len('data): u32`).
+fn is_value(tokens: &[TokenTree], i: usize) -> bool {
+    let is_call = matches!(
+        tokens.get(i + 1),
+        Some(TokenTree::Group(g)) if matches!(g.delimiter(), Delimiter::Parenthesis | Delimiter::Brace)
+    );
+    let is_keyword = matches!(
+        tokens.get(i + 1),
+        Some(TokenTree::Punct(p)) if p.as_char() == '=' && p.spacing() == Spacing::Alone
+    );
+    if is_call || is_keyword {
+        return false;
+    }
+    let Some(prev) = i.checked_sub(1) else {
+        return true;
+    };
+    match &tokens[prev] {
+        TokenTree::Punct(p) if p.as_char() == '@' => false,
+        // The entry after a label, as in `'data: value`, is a value. Otherwise
+        // this follows `::` in a label path, or is the type of an expression.
+        TokenTree::Punct(p) if p.as_char() == ':' => {
+            prev >= 2
+                && matches!(tokens[prev - 1], TokenTree::Ident(_))
+                && is_punct(&tokens[prev - 2], '\'')
+        }
+        _ => true,
+    }
+}
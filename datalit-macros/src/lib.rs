@@ -7,3 +7,13 @@ use proc_macro::TokenStream as BaseTokenStream;
 pub fn datalit(input: BaseTokenStream) -> BaseTokenStream {
     datalit_macros_internals::generate_expr_raw(input.into()).into()
 }
+
+#[proc_macro]
+pub fn datalit_array(input: BaseTokenStream) -> BaseTokenStream {
+    datalit_macros_internals::generate_array_expr_raw(input.into()).into()
+}
+
+#[proc_macro]
+pub fn datalit_template(input: BaseTokenStream) -> BaseTokenStream {
+    datalit_macros_internals::generate_template_raw(input.into()).into()
+}
@@ -0,0 +1,1070 @@
+//! A runtime counterpart to the `datalit!` macro.
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString as _},
+    vec,
+    vec::Vec,
+};
+use core::{fmt, mem};
+
+use crate::{
+    checksum::Checksum,
+    encoding::{
+        BitOrder, Endianness, FloatType, IntType, MAX_BIT_FIELD_WIDTH, TextEncoding, VarIntType,
+    },
+    layout::{DataRange, LabelError, LabelPath, Layout, LocationMap, VarFieldSizes},
+};
+
+/// An integer type with an optional explicit endianness, like `u32` or
+/// `u32_le` in `datalit!`.
+///
+/// Created from an [`IntType`], which uses the builder's endian mode, or with
+/// [`IntType::le`], [`IntType::be`] or [`IntType::ne`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntField {
+    int_type: IntType,
+    endianness: Option<Endianness>,
+}
+
+impl From<IntType> for IntField {
+    fn from(int_type: IntType) -> Self {
+        Self {
+            int_type,
+            endianness: None,
+        }
+    }
+}
+
+/// A float type with an optional explicit endianness, like `f32` or `f32_le`
+/// in `datalit!`.
+///
+/// Created from a [`FloatType`], which uses the builder's endian mode, or with
+/// [`FloatType::le`], [`FloatType::be`] or [`FloatType::ne`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FloatField {
+    float_type: FloatType,
+    endianness: Option<Endianness>,
+}
+
+impl From<FloatType> for FloatField {
+    fn from(float_type: FloatType) -> Self {
+        Self {
+            float_type,
+            endianness: None,
+        }
+    }
+}
+
+/// A text encoding with an optional explicit endianness, like `utf16` or
+/// `utf16le` in `datalit!`.
+///
+/// Created from a [`TextEncoding`], which uses the builder's endian mode, or
+/// with [`TextEncoding::le`], [`TextEncoding::be`] or [`TextEncoding::ne`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextField {
+    encoding: TextEncoding,
+    endianness: Option<Endianness>,
+}
+
+impl From<TextEncoding> for TextField {
+    fn from(encoding: TextEncoding) -> Self {
+        Self {
+            encoding,
+            endianness: None,
+        }
+    }
+}
+
+macro_rules! with_endianness {
+    ($type:ty, $field:ident, $field_name:ident) => {
+        impl $type {
+            /// This type, written little-endian.
+            pub const fn le(self) -> $field {
+                $field {
+                    $field_name: self,
+                    endianness: Some(Endianness::Little),
+                }
+            }
+
+            /// This type, written big-endian.
+            pub const fn be(self) -> $field {
+                $field {
+                    $field_name: self,
+                    endianness: Some(Endianness::Big),
+                }
+            }
+
+            /// This type, written in the target's native byte order.
+            pub const fn ne(self) -> $field {
+                $field {
+                    $field_name: self,
+                    endianness: Some(Endianness::Native),
+                }
+            }
+        }
+    };
+}
+
+with_endianness!(IntType, IntField, int_type);
+with_endianness!(FloatType, FloatField, float_type);
+with_endianness!(TextEncoding, TextField, encoding);
+
+/// A field for a value written once the data is laid out, like the type after
+/// an expression such as `len('data): u32` in `datalit!`.
+///
+/// Created from an [`IntType`] or [`IntField`], or from a [`VarIntType`],
+/// whose encoded size depends on the value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueField {
+    Int(IntField),
+    Var(VarIntType),
+}
+
+impl From<IntType> for ValueField {
+    fn from(int_type: IntType) -> Self {
+        Self::Int(int_type.into())
+    }
+}
+
+impl From<IntField> for ValueField {
+    fn from(field: IntField) -> Self {
+        Self::Int(field)
+    }
+}
+
+impl From<VarIntType> for ValueField {
+    fn from(var_int_type: VarIntType) -> Self {
+        Self::Var(var_int_type)
+    }
+}
+
+/// An error found while building data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// A value does not fit in the type it is written as.
+    OutOfRange { value: String, ty: String },
+    /// A label was referenced but never defined.
+    UndefinedLabel(String),
+    /// A label was defined more than once in the same block.
+    DuplicateLabel(String),
+    /// A label name or path that is not written as in the macro, such as
+    /// `data` or `chunk::data[2]`.
+    InvalidLabel(String),
+    /// A label that matches several labels defined in nested blocks.
+    AmbiguousLabel(String),
+    /// A label path that looks up `segment` within `label`, which does not
+    /// label a block.
+    NotABlock { label: String, segment: String },
+    /// A label whose value is needed to lay out the data, but which is only
+    /// defined after this point.
+    LabelNotLaidOut(String),
+    /// An alignment that is not a power of two.
+    InvalidAlignment(usize),
+    /// Padding to an offset the data is already past.
+    PastOffset { offset: usize, len: usize },
+    /// Padding to an offset from the start of `label` the data is already
+    /// past.
+    PastLabelOffset {
+        label: String,
+        offset: usize,
+        len: usize,
+    },
+    /// A sized block whose contents are larger than its size.
+    BlockOverflow { size: usize, len: usize },
+    /// Checksums that each cover the other.
+    CircularDependency,
+    /// A character that cannot be represented in a text encoding.
+    Unrepresentable { ch: char, encoding: TextEncoding },
+    /// A bit field width outside `1..=128`.
+    InvalidBitWidth(usize),
+    /// Bit fields that do not add up to a whole number of bytes.
+    PartialByte { bits: usize },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::OutOfRange { value, ty } => {
+                write!(f, "Value {value} out of range for {ty}")
+            }
+            BuildError::UndefinedLabel(label) => write!(f, "Label '{label}' not defined"),
+            BuildError::DuplicateLabel(label) => write!(f, "Label '{label}' already defined"),
+            BuildError::InvalidLabel(label) => write!(f, "Invalid label '{label}'"),
+            BuildError::AmbiguousLabel(label) => write!(
+                f,
+                "Label '{label}' is ambiguous; qualify it with the label of the block defining it, or select a repeat iteration"
+            ),
+            BuildError::NotABlock { label, segment } => write!(
+                f,
+                "Label '{label}' does not label a block, so it has no label '{segment}'"
+            ),
+            BuildError::LabelNotLaidOut(label) => write!(
+                f,
+                "Label '{label}' is not laid out yet; only labels defined before this point, or enclosing it, can be used here"
+            ),
+            BuildError::InvalidAlignment(alignment) => {
+                write!(f, "Alignment {alignment} must be a power of two")
+            }
+            BuildError::PastOffset { offset, len } => {
+                write!(f, "Data is already {len} bytes long, past offset {offset}")
+            }
+            BuildError::PastLabelOffset { label, offset, len } => write!(
+                f,
+                "Data is already {len} bytes past the start of '{label}', past offset {offset}"
+            ),
+            BuildError::BlockOverflow { size, len } => {
+                write!(
+                    f,
+                    "Block contents ({len} bytes) overflow its size of {size} bytes"
+                )
+            }
+            BuildError::CircularDependency => {
+                f.write_str("Circular dependency between values computed from data")
+            }
+            BuildError::Unrepresentable { ch, encoding } => {
+                write!(
+                    f,
+                    "Character {ch:?} (U+{:04X}) cannot be encoded in {encoding}",
+                    u32::from(*ch)
+                )
+            }
+            BuildError::InvalidBitWidth(width) => {
+                write!(
+                    f,
+                    "Bit field width {width} must be in the range 1..={MAX_BIT_FIELD_WIDTH}"
+                )
+            }
+            BuildError::PartialByte { bits } => {
+                write!(
+                    f,
+                    "Bit fields total {bits} bits, not a whole number of bytes"
+                )
+            }
+        }
+    }
+}
+
+impl core::error::Error for BuildError {}
+
+fn out_of_range(value: impl fmt::Display, ty: impl fmt::Display) -> BuildError {
+    BuildError::OutOfRange {
+        value: value.to_string(),
+        ty: ty.to_string(),
+    }
+}
+
+/// The error for a label use that could not be resolved.
+fn label_error(path: &LabelPath, err: LabelError) -> BuildError {
+    match err {
+        LabelError::NotDefined => BuildError::UndefinedLabel(path.to_string()),
+        LabelError::Ambiguous(_) => BuildError::AmbiguousLabel(path.to_string()),
+        LabelError::NotABlock { label, segment } => BuildError::NotABlock { label, segment },
+        LabelError::DefinedLater => BuildError::LabelNotLaidOut(path.to_string()),
+    }
+}
+
+/// The label `pstr` gives its contents, only visible within its own scope.
+const PSTR_LABEL: &str = "__datalit_pstr";
+
+type BuildLayout = Layout<BuildError, ()>;
+
+/// A field with its endianness resolved from the endian mode.
+#[derive(Debug, Clone, Copy)]
+enum ResolvedField {
+    Int(IntType, Endianness),
+    Var(VarIntType),
+}
+
+/// A value written once the data is laid out.
+#[derive(Debug, Clone)]
+enum DeferredValue {
+    Start(LabelPath),
+    End(LabelPath),
+    Len(LabelPath),
+    Count(LabelPath),
+    /// The start of a label relative to the field the value is written to.
+    Rel(LabelPath),
+    /// The start of a label relative to the start of another.
+    Offset(LabelPath, LabelPath),
+}
+
+type EvalFn = Box<dyn Fn(&LocationMap) -> Result<i128, BuildError>>;
+
+impl DeferredValue {
+    /// Records the label uses of the value, returning how to compute it once
+    /// they are resolved. `here` is the offset of the field.
+    fn process(&self, layout: &mut BuildLayout, here: usize) -> EvalFn {
+        let mut use_label = |path: &LabelPath| layout.report_label_use(path.clone(), ());
+        let range = |map: &LocationMap, label| {
+            map.get(label)
+                .expect("Label uses are resolved before patches are applied")
+        };
+        match self {
+            DeferredValue::Start(path) => {
+                let label = use_label(path);
+                Box::new(move |map| Ok(range(map, label).start() as i128))
+            }
+            DeferredValue::End(path) => {
+                let label = use_label(path);
+                Box::new(move |map| Ok(range(map, label).end() as i128))
+            }
+            DeferredValue::Len(path) => {
+                let label = use_label(path);
+                Box::new(move |map| Ok(range(map, label).size() as i128))
+            }
+            DeferredValue::Count(path) => {
+                let label = use_label(path);
+                Box::new(move |map| {
+                    let count = map
+                        .element_count(label)
+                        .expect("Every label of a builder labels a block or repeat");
+                    Ok(count as i128)
+                })
+            }
+            DeferredValue::Rel(path) => {
+                let label = use_label(path);
+                Box::new(move |map| Ok(range(map, label).start() as i128 - here as i128))
+            }
+            DeferredValue::Offset(target, base) => {
+                let target = use_label(target);
+                let base = use_label(base);
+                Box::new(move |map| {
+                    Ok(range(map, target).start() as i128 - range(map, base).start() as i128)
+                })
+            }
+        }
+    }
+}
+
+/// Writes `value` as `int_type` into `field`, which is exactly its size.
+fn write_int(
+    int_type: IntType,
+    endianness: Endianness,
+    value: i128,
+    field: &mut [u8],
+) -> Result<(), BuildError> {
+    let bytes = int_type
+        .encode_i128(value, endianness)
+        .ok_or_else(|| out_of_range(value, int_type))?;
+    field.copy_from_slice(&bytes);
+    Ok(())
+}
+
+/// An entry recorded by a [`DataBuilder`]. The entries are laid out by
+/// [`build`](DataBuilder::build), again each time a variable-size field needs
+/// more space.
+#[derive(Debug, Clone)]
+enum Op {
+    Bytes(Vec<u8>),
+    Align {
+        alignment: usize,
+        fill: u8,
+    },
+    PadTo {
+        offset: usize,
+        base: Option<LabelPath>,
+        fill: u8,
+    },
+    /// An entry padded to exactly `size` bytes.
+    Sized {
+        size: usize,
+        fill: u8,
+        contents: Box<Op>,
+    },
+    /// A block, which is a scope for the labels defined in it.
+    Block(Vec<Op>),
+    /// A scope that is not itself an element, like the one `pstr` defines for
+    /// the label of its contents.
+    Scope(Vec<Op>),
+    /// The entries of each iteration of a repeat.
+    Repeat(Vec<Vec<Op>>),
+    Label {
+        name: String,
+        entry: Box<Op>,
+    },
+    Value {
+        field: ResolvedField,
+        value: DeferredValue,
+    },
+    Checksum {
+        checksum: Checksum,
+        label: LabelPath,
+        int_type: IntType,
+        endianness: Endianness,
+    },
+}
+
+impl Op {
+    fn apply_all(ops: &[Op], layout: &mut BuildLayout) -> Result<(), BuildError> {
+        ops.iter().try_for_each(|op| op.apply(layout))
+    }
+
+    fn apply(&self, layout: &mut BuildLayout) -> Result<(), BuildError> {
+        match self {
+            Op::Bytes(bytes) => layout.append_bytes(bytes),
+            Op::Align { alignment, fill } => {
+                let len = layout.curr_offset();
+                layout.pad_bytes(len.next_multiple_of(*alignment) - len, *fill);
+            }
+            Op::PadTo { offset, base, fill } => {
+                let len = match base {
+                    Some(base) => {
+                        let start = layout.laid_out_label_start(base.clone(), ()).map_err(
+                            |err| match err {
+                                LabelError::NotDefined => {
+                                    BuildError::LabelNotLaidOut(base.to_string())
+                                }
+                                err => label_error(base, err),
+                            },
+                        )?;
+                        let len = layout.curr_offset() - start;
+                        if len > *offset {
+                            return Err(BuildError::PastLabelOffset {
+                                label: base.to_string(),
+                                offset: *offset,
+                                len,
+                            });
+                        }
+                        len
+                    }
+                    None => {
+                        let len = layout.curr_offset();
+                        if len > *offset {
+                            return Err(BuildError::PastOffset {
+                                offset: *offset,
+                                len,
+                            });
+                        }
+                        len
+                    }
+                };
+                layout.pad_bytes(offset - len, *fill);
+            }
+            Op::Sized {
+                size,
+                fill,
+                contents,
+            } => {
+                let start = layout.curr_offset();
+                contents.apply(layout)?;
+                let len = layout.curr_offset() - start;
+                if len > *size {
+                    return Err(BuildError::BlockOverflow { size: *size, len });
+                }
+                layout.pad_bytes(size - len, *fill);
+            }
+            Op::Block(ops) => {
+                layout.enter_scope();
+                Self::apply_all(ops, layout)?;
+                layout.exit_scope();
+                layout.report_element_count(ops.len());
+            }
+            Op::Scope(ops) => {
+                layout.enter_scope();
+                Self::apply_all(ops, layout)?;
+                layout.exit_scope();
+            }
+            Op::Repeat(iterations) => {
+                // As in the macro, each iteration defines its own instances
+                // of the labels in the contents.
+                layout.enter_scope();
+                for (iteration, ops) in iterations.iter().enumerate() {
+                    layout.enter_iteration_scope(iteration);
+                    Self::apply_all(ops, layout)?;
+                    layout.exit_scope();
+                }
+                layout.exit_scope();
+                layout.report_element_count(iterations.len());
+            }
+            Op::Label { name, entry } => {
+                let start = layout
+                    .begin_label_def(name, ())
+                    .map_err(|_| BuildError::DuplicateLabel(name.clone()))?;
+                entry.apply(layout)?;
+                layout.report_label_def(start);
+            }
+            Op::Value { field, value } => {
+                let eval = value.process(layout, layout.curr_offset());
+                match *field {
+                    ResolvedField::Int(int_type, endianness) => {
+                        let offset = layout.curr_offset();
+                        let size = int_type.num_bytes();
+                        layout.advance_bytes(size);
+                        layout.defer_patch_op(move |map, data| {
+                            write_int(
+                                int_type,
+                                endianness,
+                                eval(map)?,
+                                &mut data[offset..offset + size],
+                            )
+                        });
+                    }
+                    ResolvedField::Var(var_int_type) => {
+                        layout.defer_var_patch_op(move |map, min_len| {
+                            let value = eval(map)?;
+                            var_int_type
+                                .encode(value, min_len)
+                                .ok_or_else(|| out_of_range(value, var_int_type))
+                        });
+                    }
+                }
+            }
+            Op::Checksum {
+                checksum,
+                label,
+                int_type,
+                endianness,
+            } => {
+                let label = layout.report_label_use(label.clone(), ());
+                let offset = layout.curr_offset();
+                let size = int_type.num_bytes();
+                layout.advance_bytes(size);
+                let (checksum, int_type, endianness) = (*checksum, *int_type, *endianness);
+                let field = DataRange::new(offset, offset + size);
+                layout.defer_data_patch_op(field, vec![label], (), move |map, data| {
+                    let read = map
+                        .get(label)
+                        .expect("Label uses are resolved before patches are applied");
+                    let value = checksum.compute(&data[read.start()..read.end()]);
+                    write_int(
+                        int_type,
+                        endianness,
+                        value.into(),
+                        &mut data[offset..offset + size],
+                    )
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds binary data at runtime, with the same encodings, label scoping and
+/// layout as the `datalit!` macro.
+///
+/// Each method corresponds to an entry of the macro, and appends to the data
+/// in order. Values that refer to labels, such as [`len_of`](Self::len_of),
+/// are written when the data is built, so labels may be defined after they
+/// are used. As in the macro, each labeled block is a scope for the labels
+/// defined in it, and labels are referenced by paths such as `chunk::data`
+/// or `data[2]`. Errors are reported by [`build`](Self::build).
+///
+/// ```
+/// use datalit_runtime::{Checksum, DataBuilder, encoding::{Endianness, IntType}};
+///
+/// let data = DataBuilder::new()
+///     .endian(Endianness::Big)
+///     .len_of("data", IntType::U32)
+///     .label("chunk", |b| {
+///         b.bytes(b"IEND").label("data", |_| {});
+///     })
+///     .checksum(Checksum::Crc32, "chunk", IntType::U32)
+///     .build()
+///     .unwrap();
+/// assert_eq!(data, b"\0\0\0\0IEND\xAE\x42\x60\x82");
+/// ```
+#[derive(Debug, Clone)]
+pub struct DataBuilder {
+    ops: Vec<Op>,
+    endian_mode: Endianness,
+    fill_byte: u8,
+    string_encoding: TextField,
+    error: Option<BuildError>,
+}
+
+macro_rules! int_methods {
+    ($($method:ident: $type:ident as $conv:ident($value:ty) with $int_type:expr;)*) => {
+        $(
+            #[doc = concat!("Appends a `", stringify!($method), "`, like `datalit!`'s `", stringify!($method), "` suffix.")]
+            pub fn $method(&mut self, value: $type) -> &mut Self {
+                self.$conv($int_type, <$value>::from(value))
+            }
+        )*
+    };
+}
+
+macro_rules! float_methods {
+    ($($method:ident: $type:ident with $float_type:expr;)*) => {
+        $(
+            #[doc = concat!("Appends an `", stringify!($method), "`, like `datalit!`'s `", stringify!($method), "` suffix.")]
+            pub fn $method(&mut self, value: $type) -> &mut Self {
+                self.float($float_type, f64::from(value))
+            }
+        )*
+    };
+}
+
+impl DataBuilder {
+    pub fn new() -> Self {
+        Self {
+            ops: Vec::new(),
+            endian_mode: Endianness::Native,
+            fill_byte: 0x00,
+            string_encoding: TextEncoding::Utf8.into(),
+            error: None,
+        }
+    }
+
+    fn record_error(&mut self, error: BuildError) {
+        self.error.get_or_insert(error);
+    }
+
+    fn push(&mut self, op: Op) -> &mut Self {
+        self.ops.push(op);
+        self
+    }
+
+    /// Returns the entries added by `f`, instead of appending them.
+    fn record(&mut self, f: impl FnOnce(&mut Self)) -> Vec<Op> {
+        let outer = mem::take(&mut self.ops);
+        f(self);
+        mem::replace(&mut self.ops, outer)
+    }
+
+    fn label_path(&mut self, label: &str) -> Option<LabelPath> {
+        let path = LabelPath::parse(label);
+        if path.is_none() {
+            self.record_error(BuildError::InvalidLabel(label.into()));
+        }
+        path
+    }
+
+    /// Sets the endianness of values without an explicit one, like
+    /// `@endian = ...`.
+    pub fn endian(&mut self, endianness: Endianness) -> &mut Self {
+        self.endian_mode = endianness;
+        self
+    }
+
+    /// Sets the byte used for padding, like `@fill = ...`.
+    pub fn fill(&mut self, fill: u8) -> &mut Self {
+        self.fill_byte = fill;
+        self
+    }
+
+    /// Sets the encoding of [`text`](Self::text), like
+    /// `@string_encoding = ...`.
+    pub fn string_encoding(&mut self, field: impl Into<TextField>) -> &mut Self {
+        self.string_encoding = field.into();
+        self
+    }
+
+    /// Appends raw bytes.
+    pub fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.push(Op::Bytes(bytes.to_vec()))
+    }
+
+    /// Appends text in the current string encoding, like a `"..."` literal.
+    pub fn text(&mut self, text: &str) -> &mut Self {
+        self.text_as(self.string_encoding, text)
+    }
+
+    /// Appends text in the given encoding, like `utf16le("...")`.
+    pub fn text_as(&mut self, field: impl Into<TextField>, text: &str) -> &mut Self {
+        let field = field.into();
+        let endianness = field.endianness.unwrap_or(self.endian_mode);
+        match field.encoding.encode(text, endianness) {
+            Ok(bytes) => self.push(Op::Bytes(bytes)),
+            Err(ch) => {
+                self.record_error(BuildError::Unrepresentable {
+                    ch,
+                    encoding: field.encoding,
+                });
+                self
+            }
+        }
+    }
+
+    /// Appends a signed value as the given integer type.
+    pub fn int(&mut self, field: impl Into<IntField>, value: i128) -> &mut Self {
+        let field = field.into();
+        let endianness = field.endianness.unwrap_or(self.endian_mode);
+        match field.int_type.encode_i128(value, endianness) {
+            Some(bytes) => self.push(Op::Bytes(bytes)),
+            None => {
+                self.record_error(out_of_range(value, field.int_type));
+                self
+            }
+        }
+    }
+
+    /// Appends an unsigned value as the given integer type.
+    pub fn uint(&mut self, field: impl Into<IntField>, value: u128) -> &mut Self {
+        let field = field.into();
+        let endianness = field.endianness.unwrap_or(self.endian_mode);
+        match field.int_type.encode_u128(value, endianness) {
+            Some(bytes) => self.push(Op::Bytes(bytes)),
+            None => {
+                self.record_error(out_of_range(value, field.int_type));
+                self
+            }
+        }
+    }
+
+    int_methods! {
+        u8: u8 as uint(u128) with IntType::U8;
+        i8: i8 as int(i128) with IntType::I8;
+        u16: u16 as uint(u128) with IntType::U16;
+        u16_le: u16 as uint(u128) with IntType::U16.le();
+        u16_be: u16 as uint(u128) with IntType::U16.be();
+        i16: i16 as int(i128) with IntType::I16;
+        i16_le: i16 as int(i128) with IntType::I16.le();
+        i16_be: i16 as int(i128) with IntType::I16.be();
+        u32: u32 as uint(u128) with IntType::U32;
+        u32_le: u32 as uint(u128) with IntType::U32.le();
+        u32_be: u32 as uint(u128) with IntType::U32.be();
+        i32: i32 as int(i128) with IntType::I32;
+        i32_le: i32 as int(i128) with IntType::I32.le();
+        i32_be: i32 as int(i128) with IntType::I32.be();
+        u64: u64 as uint(u128) with IntType::U64;
+        u64_le: u64 as uint(u128) with IntType::U64.le();
+        u64_be: u64 as uint(u128) with IntType::U64.be();
+        i64: i64 as int(i128) with IntType::I64;
+        i64_le: i64 as int(i128) with IntType::I64.le();
+        i64_be: i64 as int(i128) with IntType::I64.be();
+        u128: u128 as uint(u128) with IntType::U128;
+        u128_le: u128 as uint(u128) with IntType::U128.le();
+        u128_be: u128 as uint(u128) with IntType::U128.be();
+        i128: i128 as int(i128) with IntType::I128;
+        i128_le: i128 as int(i128) with IntType::I128.le();
+        i128_be: i128 as int(i128) with IntType::I128.be();
+    }
+
+    /// Appends a value as the given float type, rounded to the nearest
+    /// representable value (ties to even).
+    pub fn float(&mut self, field: impl Into<FloatField>, value: f64) -> &mut Self {
+        let field = field.into();
+        if field.float_type.rounds_to_infinity(value) && value.is_finite() {
+            self.record_error(out_of_range(value, field.float_type));
+            return self;
+        }
+        let endianness = field.endianness.unwrap_or(self.endian_mode);
+        let bytes = field.float_type.encode(value, endianness);
+        self.push(Op::Bytes(bytes))
+    }
+
+    float_methods! {
+        f32: f32 with FloatType::F32;
+        f32_le: f32 with FloatType::F32.le();
+        f32_be: f32 with FloatType::F32.be();
+        f64: f64 with FloatType::F64;
+        f64_le: f64 with FloatType::F64.le();
+        f64_be: f64 with FloatType::F64.be();
+    }
+
+    /// Appends a variable-length integer, like `uleb(...)` or `varint(...)`.
+    pub fn var_int(&mut self, var_int_type: VarIntType, value: i128) -> &mut Self {
+        match var_int_type.encode(value, 1) {
+            Some(bytes) => self.push(Op::Bytes(bytes)),
+            None => {
+                self.record_error(out_of_range(value, var_int_type));
+                self
+            }
+        }
+    }
+
+    /// Appends an unsigned LEB128 value.
+    pub fn uleb(&mut self, value: u64) -> &mut Self {
+        self.var_int(VarIntType::ULeb128, value.into())
+    }
+
+    /// Appends a signed LEB128 value.
+    pub fn sleb(&mut self, value: i64) -> &mut Self {
+        self.var_int(VarIntType::SLeb128, value.into())
+    }
+
+    /// Appends a protobuf varint.
+    pub fn varint(&mut self, value: i64) -> &mut Self {
+        self.var_int(VarIntType::Varint, value.into())
+    }
+
+    /// Appends a zigzag-encoded protobuf `sint` varint.
+    pub fn zigzag(&mut self, value: i64) -> &mut Self {
+        self.var_int(VarIntType::ZigZag, value.into())
+    }
+
+    /// Appends `(value, width)` bit fields packed in the given order, like
+    /// `bits { value: width, ... }`.
+    pub fn bits(&mut self, order: BitOrder, fields: &[(u128, usize)]) -> &mut Self {
+        let total: usize = fields.iter().map(|&(_, width)| width).sum();
+        if !total.is_multiple_of(8) {
+            self.record_error(BuildError::PartialByte { bits: total });
+            return self;
+        }
+        let mut packed = vec![0; total / 8];
+        let mut offset = 0;
+        for &(value, width) in fields {
+            if !(1..=MAX_BIT_FIELD_WIDTH).contains(&width) {
+                self.record_error(BuildError::InvalidBitWidth(width));
+                return self;
+            }
+            if value > BitOrder::max_value(width) {
+                self.record_error(out_of_range(value, format_args!("{width}-bit field")));
+                return self;
+            }
+            order.write_bits(&mut packed, offset, width, value);
+            offset += width;
+        }
+        self.push(Op::Bytes(packed))
+    }
+
+    /// Pads to the next multiple of `alignment` with the fill byte, like
+    /// `align(...)`.
+    pub fn align(&mut self, alignment: usize) -> &mut Self {
+        self.align_with_fill(alignment, self.fill_byte)
+    }
+
+    /// Pads to the next multiple of `alignment` with `fill`, like
+    /// `align(..., fill = ...)`.
+    pub fn align_with_fill(&mut self, alignment: usize, fill: u8) -> &mut Self {
+        if !alignment.is_power_of_two() {
+            self.record_error(BuildError::InvalidAlignment(alignment));
+            return self;
+        }
+        self.push(Op::Align { alignment, fill })
+    }
+
+    /// Pads to an absolute offset with the fill byte, like `pad_to(...)`.
+    pub fn pad_to(&mut self, offset: usize) -> &mut Self {
+        self.pad_to_with_fill(offset, self.fill_byte)
+    }
+
+    /// Pads to an absolute offset with `fill`, like `pad_to(..., fill = ...)`.
+    pub fn pad_to_with_fill(&mut self, offset: usize, fill: u8) -> &mut Self {
+        self.push(Op::PadTo {
+            offset,
+            base: None,
+            fill,
+        })
+    }
+
+    /// Pads to an offset from the start of `label` with the fill byte, like
+    /// `pad_to(..., from = 'label)`. The label must be defined before this
+    /// point, or enclose it.
+    pub fn pad_to_from(&mut self, offset: usize, label: &str) -> &mut Self {
+        let Some(base) = self.label_path(label) else {
+            return self;
+        };
+        let fill = self.fill_byte;
+        self.push(Op::PadTo {
+            offset,
+            base: Some(base),
+            fill,
+        })
+    }
+
+    /// Appends the entries added by `f` as a block, padded to exactly `size`
+    /// bytes with the fill byte, like `sized(...) { ... }` or `fixed(...)`.
+    pub fn sized(&mut self, size: usize, f: impl FnOnce(&mut Self)) -> &mut Self {
+        self.sized_with_fill(size, self.fill_byte, f)
+    }
+
+    /// Appends the entries added by `f` as a block, padded to exactly `size`
+    /// bytes with zeroes, like `zpad(...)`.
+    pub fn zpad(&mut self, size: usize, f: impl FnOnce(&mut Self)) -> &mut Self {
+        self.sized_with_fill(size, 0x00, f)
+    }
+
+    fn sized_with_fill(&mut self, size: usize, fill: u8, f: impl FnOnce(&mut Self)) -> &mut Self {
+        let ops = self.record(f);
+        self.push(Op::Sized {
+            size,
+            fill,
+            contents: Box::new(Op::Block(ops)),
+        })
+    }
+
+    /// Appends the entries added by `f` as a block, preceded by their length,
+    /// like `pstr(...)`.
+    pub fn pstr(&mut self, field: impl Into<ValueField>, f: impl FnOnce(&mut Self)) -> &mut Self {
+        let field = self.resolve_field(field.into());
+        let ops = self.record(f);
+        self.push(Op::Scope(vec![
+            Op::Value {
+                field,
+                value: DeferredValue::Len(LabelPath::new(PSTR_LABEL, None)),
+            },
+            Op::Label {
+                name: PSTR_LABEL.into(),
+                entry: Box::new(Op::Block(ops)),
+            },
+        ]))
+    }
+
+    /// Appends the entries added by `f` as a block, like `{ ... }`.
+    ///
+    /// The block is a scope for the labels defined in it.
+    pub fn block(&mut self, f: impl FnOnce(&mut Self)) -> &mut Self {
+        let ops = self.record(f);
+        self.push(Op::Block(ops))
+    }
+
+    /// Appends the entries added by `f` for each iteration from `0` to
+    /// `count`, like `[... ; count]` or `[for i in 0..count { ... }]`.
+    ///
+    /// Each iteration is a scope for the labels defined in it, whose
+    /// instances are selected by index, as in `data[2]`.
+    pub fn repeat(&mut self, count: usize, f: impl FnMut(&mut Self, usize)) -> &mut Self {
+        let op = self.record_repeat(count, f);
+        self.push(op)
+    }
+
+    /// Appends a repeat like [`repeat`](Self::repeat), labeled like
+    /// `'name: [...]`, so that [`count_of`](Self::count_of) counts its
+    /// iterations.
+    pub fn labeled_repeat(
+        &mut self,
+        name: &str,
+        count: usize,
+        f: impl FnMut(&mut Self, usize),
+    ) -> &mut Self {
+        let op = self.record_repeat(count, f);
+        self.push_label(name, op)
+    }
+
+    fn record_repeat(&mut self, count: usize, mut f: impl FnMut(&mut Self, usize)) -> Op {
+        let iterations = (0..count)
+            .map(|iteration| self.record(|b| f(b, iteration)))
+            .collect();
+        Op::Repeat(iterations)
+    }
+
+    /// Labels the entries added by `f`, like `'name: { ... }`.
+    ///
+    /// The block is a scope for the labels defined in it, which can be
+    /// referenced from outside it as `name::inner`.
+    pub fn label(&mut self, name: &str, f: impl FnOnce(&mut Self)) -> &mut Self {
+        let ops = self.record(f);
+        self.push_label(name, Op::Block(ops))
+    }
+
+    fn push_label(&mut self, name: &str, entry: Op) -> &mut Self {
+        let is_name = LabelPath::parse(name)
+            .is_some_and(|path| path.segments().is_empty() && path.root().index.is_none());
+        if !is_name {
+            self.record_error(BuildError::InvalidLabel(name.into()));
+            return self;
+        }
+        self.push(Op::Label {
+            name: name.into(),
+            entry: Box::new(entry),
+        })
+    }
+
+    fn resolve_field(&self, field: ValueField) -> ResolvedField {
+        match field {
+            ValueField::Int(field) => {
+                ResolvedField::Int(field.int_type, field.endianness.unwrap_or(self.endian_mode))
+            }
+            ValueField::Var(var_int_type) => ResolvedField::Var(var_int_type),
+        }
+    }
+
+    fn defer(&mut self, field: ValueField, value: Option<DeferredValue>) -> &mut Self {
+        let Some(value) = value else {
+            return self;
+        };
+        let field = self.resolve_field(field);
+        self.push(Op::Value { field, value })
+    }
+
+    /// Appends the start offset of a label, like `start('label): ...`.
+    pub fn start_of(&mut self, label: &str, field: impl Into<ValueField>) -> &mut Self {
+        let value = self.label_path(label).map(DeferredValue::Start);
+        self.defer(field.into(), value)
+    }
+
+    /// Appends the end offset of a label, like `end('label): ...`.
+    pub fn end_of(&mut self, label: &str, field: impl Into<ValueField>) -> &mut Self {
+        let value = self.label_path(label).map(DeferredValue::End);
+        self.defer(field.into(), value)
+    }
+
+    /// Appends the length of a label, like `len('label): ...`.
+    pub fn len_of(&mut self, label: &str, field: impl Into<ValueField>) -> &mut Self {
+        let value = self.label_path(label).map(DeferredValue::Len);
+        self.defer(field.into(), value)
+    }
+
+    /// Appends the number of elements of a labeled block or repeat, like
+    /// `count('label): ...`.
+    pub fn count_of(&mut self, label: &str, field: impl Into<ValueField>) -> &mut Self {
+        let value = self.label_path(label).map(DeferredValue::Count);
+        self.defer(field.into(), value)
+    }
+
+    /// Appends the start of a label relative to the start of the field, like
+    /// `rel('label): ...`.
+    pub fn rel_of(&mut self, label: &str, field: impl Into<ValueField>) -> &mut Self {
+        let value = self.label_path(label).map(DeferredValue::Rel);
+        self.defer(field.into(), value)
+    }
+
+    /// Appends the start of `target` relative to the start of `base`, like
+    /// `offset('target, from = 'base): ...`.
+    pub fn offset_of(
+        &mut self,
+        target: &str,
+        base: &str,
+        field: impl Into<ValueField>,
+    ) -> &mut Self {
+        let target = self.label_path(target);
+        let base = self.label_path(base);
+        let value = target
+            .zip(base)
+            .map(|(target, base)| DeferredValue::Offset(target, base));
+        self.defer(field.into(), value)
+    }
+
+    /// Appends a checksum of the final bytes of a label, like
+    /// `crc32('label): ...`.
+    pub fn checksum(
+        &mut self,
+        checksum: Checksum,
+        label: &str,
+        field: impl Into<IntField>,
+    ) -> &mut Self {
+        let field = field.into();
+        let Some(label) = self.label_path(label) else {
+            return self;
+        };
+        self.push(Op::Checksum {
+            checksum,
+            label,
+            int_type: field.int_type,
+            endianness: field.endianness.unwrap_or(self.endian_mode),
+        })
+    }
+
+    /// Lays out the data, returning it with every label-dependent value
+    /// written.
+    pub fn build(&self) -> Result<Vec<u8>, BuildError> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        // As in the macro, variable-size fields start out a single byte
+        // long, and the data is laid out again until each one fits.
+        let mut sizes = VarFieldSizes::new();
+        let mut layout = loop {
+            let mut layout = BuildLayout::new(sizes);
+            Op::apply_all(&self.ops, &mut layout)?;
+            if let Some((path, (), err)) = layout.resolve_label_uses().into_iter().next() {
+                return Err(label_error(path, err));
+            }
+            match layout.apply_patches()? {
+                Some(grown) => sizes = grown,
+                None => break layout,
+            }
+        };
+        layout.apply_data_patches(|_| BuildError::CircularDependency)?;
+        Ok(layout.into_data())
+    }
+}
+
+impl Default for DataBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,146 @@
+//! Checksum algorithms that can be computed over labeled ranges.
+
+/// Parameters of a CRC in the Rocksoft model.
+struct CrcParams {
+    width: u32,
+    poly: u64,
+    init: u64,
+    reflect: bool,
+    xor_out: u64,
+}
+
+impl CrcParams {
+    fn mask(&self) -> u64 {
+        u64::MAX >> (64 - self.width)
+    }
+
+    fn compute(&self, data: &[u8]) -> u64 {
+        let mask = self.mask();
+        let top_bit = 1 << (self.width - 1);
+        let mut crc = self.init;
+        if self.reflect {
+            // Processing the reflected register LSB-first with a reflected
+            // polynomial is equivalent to reflecting every input byte and the
+            // final value.
+            let poly = self.poly.reverse_bits() >> (64 - self.width);
+            crc = crc.reverse_bits() >> (64 - self.width);
+            for &byte in data {
+                crc ^= u64::from(byte);
+                for _ in 0..8 {
+                    crc = if crc & 1 != 0 {
+                        (crc >> 1) ^ poly
+                    } else {
+                        crc >> 1
+                    };
+                }
+            }
+        } else {
+            for &byte in data {
+                crc ^= u64::from(byte) << (self.width - 8);
+                for _ in 0..8 {
+                    crc = if crc & top_bit != 0 {
+                        ((crc << 1) ^ self.poly) & mask
+                    } else {
+                        (crc << 1) & mask
+                    };
+                }
+            }
+        }
+        (crc ^ self.xor_out) & mask
+    }
+}
+
+/// A checksum over a range of bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    /// CRC-8 (SMBus): poly 0x07, init 0x00.
+    Crc8,
+    /// CRC-16/CCITT-FALSE: poly 0x1021, init 0xFFFF, not reflected.
+    Crc16Ccitt,
+    /// CRC-16/XMODEM: poly 0x1021, init 0x0000, not reflected.
+    Crc16Xmodem,
+    /// CRC-16/KERMIT: poly 0x1021, init 0x0000, reflected.
+    Crc16Kermit,
+    /// CRC-16/ARC: poly 0x8005, init 0x0000, reflected.
+    Crc16Arc,
+    /// CRC-16/MODBUS: poly 0x8005, init 0xFFFF, reflected.
+    Crc16Modbus,
+    /// CRC-32 (ISO-HDLC), as used by PNG, ZIP, gzip and Ethernet.
+    Crc32,
+    /// CRC-32C (Castagnoli), as used by iSCSI, SCTP and ext4.
+    Crc32c,
+    /// Adler-32, as used by zlib.
+    Adler32,
+    /// The sum of all bytes, modulo 256.
+    Sum8,
+    /// The XOR of all bytes.
+    Xor8,
+    /// The Internet checksum (RFC 1071): the ones' complement of the ones'
+    /// complement sum of big-endian 16-bit words.
+    Inet,
+}
+
+impl Checksum {
+    /// Looks up a checksum by its name in `datalit!`, e.g. `crc32`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "crc8" => Some(Checksum::Crc8),
+            "crc16_ccitt" => Some(Checksum::Crc16Ccitt),
+            "crc16_xmodem" => Some(Checksum::Crc16Xmodem),
+            "crc16_kermit" => Some(Checksum::Crc16Kermit),
+            "crc16_arc" => Some(Checksum::Crc16Arc),
+            "crc16_modbus" => Some(Checksum::Crc16Modbus),
+            "crc32" => Some(Checksum::Crc32),
+            "crc32c" => Some(Checksum::Crc32c),
+            "adler32" => Some(Checksum::Adler32),
+            "sum8" => Some(Checksum::Sum8),
+            "xor8" => Some(Checksum::Xor8),
+            "inet_checksum" => Some(Checksum::Inet),
+            _ => None,
+        }
+    }
+
+    /// Computes the checksum of `data`.
+    pub fn compute(self, data: &[u8]) -> u64 {
+        let crc = |width, poly, init, reflect, xor_out| {
+            CrcParams {
+                width,
+                poly,
+                init,
+                reflect,
+                xor_out,
+            }
+            .compute(data)
+        };
+        match self {
+            Checksum::Crc8 => crc(8, 0x07, 0x00, false, 0x00),
+            Checksum::Crc16Ccitt => crc(16, 0x1021, 0xFFFF, false, 0x0000),
+            Checksum::Crc16Xmodem => crc(16, 0x1021, 0x0000, false, 0x0000),
+            Checksum::Crc16Kermit => crc(16, 0x1021, 0x0000, true, 0x0000),
+            Checksum::Crc16Arc => crc(16, 0x8005, 0x0000, true, 0x0000),
+            Checksum::Crc16Modbus => crc(16, 0x8005, 0xFFFF, true, 0x0000),
+            Checksum::Crc32 => crc(32, 0x04C1_1DB7, 0xFFFF_FFFF, true, 0xFFFF_FFFF),
+            Checksum::Crc32c => crc(32, 0x1EDC_6F41, 0xFFFF_FFFF, true, 0xFFFF_FFFF),
+            Checksum::Adler32 => {
+                const MOD_ADLER: u64 = 65521;
+                let (a, b) = data.iter().fold((1u64, 0u64), |(a, b), &byte| {
+                    let a = (a + u64::from(byte)) % MOD_ADLER;
+                    (a, (b + a) % MOD_ADLER)
+                });
+                (b << 16) | a
+            }
+            Checksum::Sum8 => data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)).into(),
+            Checksum::Xor8 => data.iter().fold(0u8, |sum, &b| sum ^ b).into(),
+            Checksum::Inet => {
+                let mut sum: u64 = data
+                    .chunks(2)
+                    .map(|word| u64::from(word[0]) << 8 | word.get(1).copied().map_or(0, u64::from))
+                    .sum();
+                while sum > 0xFFFF {
+                    sum = (sum & 0xFFFF) + (sum >> 16);
+                }
+                !sum & 0xFFFF
+            }
+        }
+    }
+}
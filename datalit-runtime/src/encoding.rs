@@ -0,0 +1,585 @@
+//! Byte encodings of the values supported by `datalit`.
+//!
+//! These are shared between the `datalit!` macro, which encodes values at
+//! compile time, and [`DataBuilder`](crate::DataBuilder), so that both always
+//! produce the same bytes.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+fn consume_suffix(suffix: &mut &str, to_consume: &str) -> bool {
+    if suffix.ends_with(to_consume) {
+        let trimmed = suffix.trim_end_matches(to_consume);
+        let trimmed = trimmed.trim_end_matches('_');
+        *suffix = trimmed;
+        true
+    } else {
+        false
+    }
+}
+
+/// The widest integer type that can be named with a `uN` / `iN` suffix.
+const MAX_INT_BITS: usize = 128;
+
+/// The byte order of a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+    Native,
+}
+
+impl Endianness {
+    /// Strips an `le`, `be` or `ne` suffix (optionally preceded by `_`) from
+    /// `suffix`, returning the endianness it names.
+    pub fn parse_from_suffix(suffix: &mut &str) -> Option<Self> {
+        if consume_suffix(suffix, "le") {
+            Some(Endianness::Little)
+        } else if consume_suffix(suffix, "be") {
+            Some(Endianness::Big)
+        } else if consume_suffix(suffix, "ne") {
+            Some(Endianness::Native)
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if this is little-endian. [`Native`](Endianness::Native)
+    /// is the byte order of the machine running this code: the compiling host
+    /// for the `datalit!` macro, and the target for [`DataBuilder`].
+    ///
+    /// [`DataBuilder`]: crate::DataBuilder
+    pub fn is_little(self) -> bool {
+        match self {
+            Endianness::Little => true,
+            Endianness::Big => false,
+            Endianness::Native => cfg!(target_endian = "little"),
+        }
+    }
+
+    /// Reorders little-endian `bytes` in place into this byte order.
+    pub fn order_le_bytes(self, bytes: &mut [u8]) {
+        if !self.is_little() {
+            bytes.reverse();
+        }
+    }
+}
+
+/// An integer type of any whole-byte width up to 128 bits.
+///
+/// Named in suffixes as `uN` / `iN` where `N` is a multiple of 8 (e.g. `u24`,
+/// `i48`), or as `usize` / `isize`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntType {
+    signed: bool,
+    num_bytes: usize,
+}
+
+impl IntType {
+    pub const U8: Self = Self::unsigned(1);
+    pub const U16: Self = Self::unsigned(2);
+    pub const U32: Self = Self::unsigned(4);
+    pub const U64: Self = Self::unsigned(8);
+    pub const U128: Self = Self::unsigned(16);
+    pub const I8: Self = Self::signed(1);
+    pub const I16: Self = Self::signed(2);
+    pub const I32: Self = Self::signed(4);
+    pub const I64: Self = Self::signed(8);
+    pub const I128: Self = Self::signed(16);
+
+    /// An unsigned integer type of `num_bytes` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_bytes` is not in `1..=16`.
+    pub const fn unsigned(num_bytes: usize) -> Self {
+        assert!(num_bytes >= 1 && num_bytes * 8 <= MAX_INT_BITS);
+        Self {
+            signed: false,
+            num_bytes,
+        }
+    }
+
+    /// A signed integer type of `num_bytes` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_bytes` is not in `1..=16`.
+    pub const fn signed(num_bytes: usize) -> Self {
+        assert!(num_bytes >= 1 && num_bytes * 8 <= MAX_INT_BITS);
+        Self {
+            signed: true,
+            num_bytes,
+        }
+    }
+
+    /// Parses a type name such as `u32`, `i24` or `usize`.
+    pub fn from_suffix(suffix: &str) -> Option<Self> {
+        let (signed, width) = if let Some(width) = suffix.strip_prefix('u') {
+            (false, width)
+        } else if let Some(width) = suffix.strip_prefix('i') {
+            (true, width)
+        } else {
+            return None;
+        };
+
+        if width == "size" {
+            return Some(IntType {
+                signed,
+                num_bytes: core::mem::size_of::<usize>(),
+            });
+        }
+
+        if width.is_empty() || width.starts_with('0') || !width.bytes().all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+        let bits: usize = width.parse().ok()?;
+        if bits == 0 || bits > MAX_INT_BITS || !bits.is_multiple_of(8) {
+            return None;
+        }
+        Some(IntType {
+            signed,
+            num_bytes: bits / 8,
+        })
+    }
+
+    pub fn num_bytes(self) -> usize {
+        self.num_bytes
+    }
+
+    pub fn is_signed(self) -> bool {
+        self.signed
+    }
+
+    pub fn num_bits(self) -> usize {
+        self.num_bytes * 8
+    }
+
+    /// Returns true if `value` is representable by this type.
+    pub fn contains_i128(self, value: i128) -> bool {
+        match u128::try_from(value) {
+            Ok(value) => self.contains_u128(value),
+            Err(_) => {
+                self.signed
+                    && (self.num_bits() == 128 || value >= -(1i128 << (self.num_bits() - 1)))
+            }
+        }
+    }
+
+    /// Returns true if `value` is representable by this type.
+    pub fn contains_u128(self, value: u128) -> bool {
+        let value_bits = if self.signed {
+            self.num_bits() - 1
+        } else {
+            self.num_bits()
+        };
+        value_bits == 128 || value >> value_bits == 0
+    }
+
+    /// Encodes `value`, or returns `None` if it is out of range.
+    pub fn encode_i128(self, value: i128, endianness: Endianness) -> Option<Vec<u8>> {
+        self.contains_i128(value)
+            .then(|| self.encode_le_bytes(value.to_le_bytes(), endianness))
+    }
+
+    /// Encodes `value`, or returns `None` if it is out of range.
+    pub fn encode_u128(self, value: u128, endianness: Endianness) -> Option<Vec<u8>> {
+        self.contains_u128(value)
+            .then(|| self.encode_le_bytes(value.to_le_bytes(), endianness))
+    }
+
+    /// Truncates the two's complement `le_bytes` of an in-range value to
+    /// the width of this type.
+    fn encode_le_bytes(self, le_bytes: [u8; 16], endianness: Endianness) -> Vec<u8> {
+        let mut bytes = le_bytes[..self.num_bytes].to_vec();
+        endianness.order_le_bytes(&mut bytes);
+        bytes
+    }
+}
+
+impl fmt::Display for IntType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = if self.signed { 'i' } else { 'u' };
+        write!(f, "{}{}", prefix, self.num_bits())
+    }
+}
+
+/// Rounds `value` to the nearest binary floating-point number with the given
+/// exponent and mantissa widths (ties to even), returning its bit pattern.
+///
+/// Literals are first parsed as `f64` and then rounded again here. This double
+/// rounding never changes the result for the formats supported here, as `f64`
+/// carries more than twice their precision plus two bits.
+fn f64_to_narrow_bits(value: f64, exp_bits: u32, mant_bits: u32) -> u64 {
+    let bits = value.to_bits();
+    let sign_out = (bits >> 63) << (exp_bits + mant_bits);
+    let exp = ((bits >> 52) & 0x7FF) as i64;
+    let mant = bits & ((1 << 52) - 1);
+    let exp_max = (1u64 << exp_bits) - 1;
+
+    if exp == 0x7FF {
+        // Infinity, or a quiet NaN.
+        let nan_bit = if mant != 0 { 1 << (mant_bits - 1) } else { 0 };
+        return sign_out | (exp_max << mant_bits) | nan_bit;
+    }
+    if exp == 0 {
+        // Zero. `f64` subnormals are far below the smallest subnormal of any
+        // narrower format, so they also round to zero.
+        return sign_out;
+    }
+
+    let bias_out = (1i64 << (exp_bits - 1)) - 1;
+    let sig = mant | (1 << 52);
+    let mut exp_out = exp - 1023 + bias_out;
+    let mut shift = 52 - i64::from(mant_bits);
+    if exp_out <= 0 {
+        // Subnormal in the output format.
+        shift += 1 - exp_out;
+        exp_out = 0;
+    }
+    if shift > 53 {
+        return sign_out;
+    }
+
+    let shift = shift as u32;
+    let truncated = sig >> shift;
+    let rem = sig & ((1 << shift) - 1);
+    let half = 1 << (shift - 1);
+    let mut rounded = truncated;
+    if rem > half || (rem == half && truncated & 1 == 1) {
+        rounded += 1;
+    }
+
+    if exp_out == 0 {
+        // A subnormal that rounds up to the smallest normal carries into the
+        // exponent field naturally.
+        return sign_out | rounded;
+    }
+
+    let mut exp_out = exp_out as u64;
+    if rounded >> (mant_bits + 1) != 0 {
+        rounded >>= 1;
+        exp_out += 1;
+    }
+    if exp_out >= exp_max {
+        return sign_out | (exp_max << mant_bits);
+    }
+    sign_out | (exp_out << mant_bits) | (rounded & ((1 << mant_bits) - 1))
+}
+
+/// A binary floating-point type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatType {
+    /// IEEE 754 half precision.
+    F16,
+    /// bfloat16: the upper half of an `f32`.
+    BF16,
+    F32,
+    F64,
+}
+
+impl FloatType {
+    pub fn from_suffix(suffix: &str) -> Option<Self> {
+        match suffix {
+            "f16" => Some(FloatType::F16),
+            "bf16" => Some(FloatType::BF16),
+            "f32" => Some(FloatType::F32),
+            "f64" => Some(FloatType::F64),
+            _ => None,
+        }
+    }
+
+    pub fn num_bytes(self) -> usize {
+        match self {
+            FloatType::F16 | FloatType::BF16 => 2,
+            FloatType::F32 => 4,
+            FloatType::F64 => 8,
+        }
+    }
+
+    fn to_bits(self, value: f64) -> u64 {
+        match self {
+            FloatType::F16 => f64_to_narrow_bits(value, 5, 10),
+            FloatType::BF16 => f64_to_narrow_bits(value, 8, 7),
+            FloatType::F32 => f64_to_narrow_bits(value, 8, 23),
+            FloatType::F64 => value.to_bits(),
+        }
+    }
+
+    /// Returns true if `value` is infinite once rounded to this type, e.g.
+    /// `1e6` as an `f16`.
+    pub fn rounds_to_infinity(self, value: f64) -> bool {
+        let (exp_bits, mant_bits) = match self {
+            FloatType::F16 => (5, 10),
+            FloatType::BF16 => (8, 7),
+            FloatType::F32 => (8, 23),
+            FloatType::F64 => (11, 52),
+        };
+        let magnitude = self.to_bits(value) & ((1 << (exp_bits + mant_bits)) - 1);
+        magnitude == ((1 << exp_bits) - 1) << mant_bits
+    }
+
+    /// Encodes `value`, rounded to the nearest value of this type (ties to
+    /// even).
+    pub fn encode(self, value: f64, endianness: Endianness) -> Vec<u8> {
+        let mut bytes = self.to_bits(value).to_le_bytes()[..self.num_bytes()].to_vec();
+        endianness.order_le_bytes(&mut bytes);
+        bytes
+    }
+}
+
+impl fmt::Display for FloatType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FloatType::F16 => "f16",
+            FloatType::BF16 => "bf16",
+            FloatType::F32 => "f32",
+            FloatType::F64 => "f64",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A variable-length integer encoding, whose size depends on the value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarIntType {
+    /// Unsigned LEB128, as used by DWARF and WebAssembly.
+    ULeb128,
+    /// Signed LEB128.
+    SLeb128,
+    /// A protobuf varint: unsigned LEB128, with negative values encoded as
+    /// their 64-bit two's complement (always 10 bytes).
+    Varint,
+    /// A protobuf `sint` varint: zigzag-mapped, then unsigned LEB128.
+    ZigZag,
+}
+
+impl VarIntType {
+    pub fn from_suffix(suffix: &str) -> Option<Self> {
+        match suffix {
+            "uleb" | "uleb128" => Some(VarIntType::ULeb128),
+            "sleb" | "sleb128" => Some(VarIntType::SLeb128),
+            "varint" => Some(VarIntType::Varint),
+            "zigzag" => Some(VarIntType::ZigZag),
+            _ => None,
+        }
+    }
+
+    /// The range of values this encoding can represent.
+    pub fn value_range(self) -> (i128, i128) {
+        match self {
+            VarIntType::ULeb128 => (0, u64::MAX.into()),
+            VarIntType::SLeb128 | VarIntType::ZigZag => (i64::MIN.into(), i64::MAX.into()),
+            VarIntType::Varint => (i64::MIN.into(), u64::MAX.into()),
+        }
+    }
+
+    /// Encodes `value`, padding the encoding with redundant continuation
+    /// bytes so that it is at least `min_len` bytes long. Returns `None` if
+    /// `value` is out of range.
+    pub fn encode(self, value: i128, min_len: usize) -> Option<Vec<u8>> {
+        let (min, max) = self.value_range();
+        if !(min..=max).contains(&value) {
+            return None;
+        }
+        Some(match self {
+            VarIntType::ULeb128 => encode_leb128(value, min_len),
+            VarIntType::SLeb128 => encode_leb128_signed(value, min_len),
+            VarIntType::Varint => {
+                let raw = if value < 0 {
+                    value + (1i128 << 64)
+                } else {
+                    value
+                };
+                encode_leb128(raw, min_len)
+            }
+            VarIntType::ZigZag => {
+                let raw = if value < 0 { -2 * value - 1 } else { 2 * value };
+                encode_leb128(raw, min_len)
+            }
+        })
+    }
+}
+
+impl fmt::Display for VarIntType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            VarIntType::ULeb128 => "uleb128",
+            VarIntType::SLeb128 => "sleb128",
+            VarIntType::Varint => "varint",
+            VarIntType::ZigZag => "zigzag",
+        };
+        f.write_str(name)
+    }
+}
+
+/// An encoding of text, used for string literals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Utf8,
+    Utf16,
+    Utf32,
+    /// ISO 8859-1, which encodes the characters up to U+00FF as one byte each.
+    Latin1,
+}
+
+impl TextEncoding {
+    /// Parses the name of an encoding, without any endianness suffix.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "utf8" => Some(TextEncoding::Utf8),
+            "utf16" => Some(TextEncoding::Utf16),
+            "utf32" => Some(TextEncoding::Utf32),
+            "latin1" => Some(TextEncoding::Latin1),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the encoding has code units wider than a byte, so
+    /// depends on the endianness.
+    pub fn has_byte_order(self) -> bool {
+        matches!(self, TextEncoding::Utf16 | TextEncoding::Utf32)
+    }
+
+    /// Encodes `text`. Returns the first character the encoding cannot
+    /// represent as an error.
+    pub fn encode(self, text: &str, endianness: Endianness) -> Result<Vec<u8>, char> {
+        fn code_units<const N: usize>(
+            units: impl Iterator<Item = [u8; N]>,
+            endianness: Endianness,
+        ) -> Vec<u8> {
+            units
+                .flat_map(|mut unit| {
+                    endianness.order_le_bytes(&mut unit);
+                    unit
+                })
+                .collect()
+        }
+
+        match self {
+            TextEncoding::Utf8 => Ok(text.as_bytes().to_vec()),
+            TextEncoding::Utf16 => Ok(code_units(
+                text.encode_utf16().map(u16::to_le_bytes),
+                endianness,
+            )),
+            TextEncoding::Utf32 => Ok(code_units(
+                text.chars().map(|c| u32::from(c).to_le_bytes()),
+                endianness,
+            )),
+            TextEncoding::Latin1 => text
+                .chars()
+                .map(|c| u8::try_from(c).map_err(|_| c))
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TextEncoding::Utf8 => "utf8",
+            TextEncoding::Utf16 => "utf16",
+            TextEncoding::Utf32 => "utf32",
+            TextEncoding::Latin1 => "latin1",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The widest bit field that can be packed with a [`BitOrder`].
+pub const MAX_BIT_FIELD_WIDTH: usize = 128;
+
+/// The order in which consecutive bit fields fill bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOrder {
+    /// Fields fill each byte from its most significant bit, and are written
+    /// most significant bit first, as in network protocol headers.
+    MsbFirst,
+    /// Fields fill each byte from its least significant bit, and are written
+    /// least significant bit first, as in DEFLATE streams.
+    LsbFirst,
+}
+
+impl BitOrder {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "msb" => Some(BitOrder::MsbFirst),
+            "lsb" => Some(BitOrder::LsbFirst),
+            _ => None,
+        }
+    }
+
+    /// The largest value of a field `width` bits wide.
+    pub fn max_value(width: usize) -> u128 {
+        assert!((1..=MAX_BIT_FIELD_WIDTH).contains(&width));
+        u128::MAX >> (MAX_BIT_FIELD_WIDTH - width)
+    }
+
+    /// Writes the low `width` bits of `value` as the field starting `offset`
+    /// bits into `dest`, replacing the bits already there.
+    pub fn write_bits(self, dest: &mut [u8], offset: usize, width: usize, value: u128) {
+        assert!(width <= MAX_BIT_FIELD_WIDTH);
+        for i in 0..width {
+            let bit = match self {
+                BitOrder::MsbFirst => (value >> (width - 1 - i)) & 1,
+                BitOrder::LsbFirst => (value >> i) & 1,
+            };
+            let pos = offset + i;
+            let mask = match self {
+                BitOrder::MsbFirst => 0x80 >> (pos % 8),
+                BitOrder::LsbFirst => 1 << (pos % 8),
+            };
+            if bit != 0 {
+                dest[pos / 8] |= mask;
+            } else {
+                dest[pos / 8] &= !mask;
+            }
+        }
+    }
+}
+
+impl fmt::Display for BitOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BitOrder::MsbFirst => "msb",
+            BitOrder::LsbFirst => "lsb",
+        };
+        f.write_str(name)
+    }
+}
+
+fn encode_leb128(mut value: i128, min_len: usize) -> Vec<u8> {
+    assert!(value >= 0);
+    let mut bytes = Vec::new();
+    loop {
+        let mut byte = (value & 0x7F) as u8;
+        value >>= 7;
+        let done = value == 0 && bytes.len() + 1 >= min_len;
+        if !done {
+            byte |= 0x80;
+        }
+        bytes.push(byte);
+        if done {
+            return bytes;
+        }
+    }
+}
+
+fn encode_leb128_signed(mut value: i128, min_len: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    loop {
+        let mut byte = (value & 0x7F) as u8;
+        value >>= 7;
+        let sign_bit_set = byte & 0x40 != 0;
+        let complete = (value == 0 && !sign_bit_set) || (value == -1 && sign_bit_set);
+        let done = complete && bytes.len() + 1 >= min_len;
+        if !done {
+            byte |= 0x80;
+        }
+        bytes.push(byte);
+        if done {
+            return bytes;
+        }
+    }
+}
@@ -0,0 +1,842 @@
+//! The layout engine shared by `datalit!` and [`DataBuilder`](crate::DataBuilder).
+//!
+//! Data is laid out by appending bytes and reserving space for values that
+//! depend on labels. Once every label is defined, label uses are resolved and
+//! the reserved values are written by patches. Variable-size fields start out
+//! a single byte long; if one does not fit, the data must be laid out again
+//! with the sizes returned by [`Layout::apply_patches`].
+//!
+//! This module is public for the macro's use only, and is not a stable API.
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, btree_map::Entry},
+    string::String,
+    vec,
+    vec::Vec,
+};
+use core::fmt;
+
+#[derive(Clone, Copy, Debug)]
+pub struct DataRange {
+    start: usize,
+    end: usize,
+}
+
+impl DataRange {
+    #[must_use]
+    pub fn new(start: usize, end: usize) -> Self {
+        assert!(start <= end);
+        Self { start, end }
+    }
+
+    #[must_use]
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    #[must_use]
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    #[must_use]
+    pub fn size(&self) -> usize {
+        self.end - self.start
+    }
+
+    #[must_use]
+    pub fn overlaps(&self, other: DataRange) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
+/// A use of a label, such as the `'data` in `len('data)`.
+///
+/// Labels are resolved once all of them have been defined, as they may be
+/// used before their definition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LabelRef(usize);
+
+impl LabelRef {
+    #[must_use]
+    pub fn new(index: usize) -> Self {
+        Self(index)
+    }
+}
+
+/// The ranges of the labels referenced by each [`LabelRef`], and the element
+/// counts of those labeling blocks or repeats.
+pub struct LocationMap {
+    ranges: BTreeMap<LabelRef, DataRange>,
+    element_counts: BTreeMap<LabelRef, usize>,
+}
+
+impl LocationMap {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            ranges: BTreeMap::new(),
+            element_counts: BTreeMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, label: LabelRef, range: DataRange, element_count: Option<usize>) {
+        let had_value = self.ranges.insert(label, range).is_some();
+        assert!(!had_value, "Label use resolved twice in LocationMap");
+        if let Some(count) = element_count {
+            self.element_counts.insert(label, count);
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, label: LabelRef) -> Option<DataRange> {
+        self.ranges.get(&label).copied()
+    }
+
+    /// The number of elements of the block or repeat labeled by `label`.
+    #[must_use]
+    pub fn element_count(&self, label: LabelRef) -> Option<usize> {
+        self.element_counts.get(&label).copied()
+    }
+}
+
+impl Default for LocationMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One part of a [`LabelPath`]: a label name, optionally selecting the
+/// instance defined in one iteration of a repeat, as in `data[2]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathSegment {
+    pub name: String,
+    pub index: Option<usize>,
+}
+
+impl fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        if let Some(index) = self.index {
+            write!(f, "[{index}]")?;
+        }
+        Ok(())
+    }
+}
+
+/// A reference to a label, optionally qualified by the label of the block it
+/// is defined in, e.g. `data` or `chunk1::data`.
+///
+/// Displayed as written in the macro, without the leading quote.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LabelPath {
+    root: PathSegment,
+    segments: Vec<PathSegment>,
+}
+
+impl LabelPath {
+    #[must_use]
+    pub fn new(name: impl Into<String>, index: Option<usize>) -> Self {
+        Self {
+            root: PathSegment {
+                name: name.into(),
+                index,
+            },
+            segments: Vec::new(),
+        }
+    }
+
+    /// Adds a label looked up within the block of the last label of the path.
+    pub fn push(&mut self, name: impl Into<String>, index: Option<usize>) {
+        self.segments.push(PathSegment {
+            name: name.into(),
+            index,
+        });
+    }
+
+    /// Parses a path written as in the macro without the leading quote, such
+    /// as `chunk1::data[2]`. Returns `None` if it is malformed.
+    #[must_use]
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.split("::").map(|part| {
+            let (name, index) = match part.strip_suffix(']') {
+                Some(rest) => {
+                    let (name, index) = rest.split_once('[')?;
+                    (name, Some(index.parse().ok()?))
+                }
+                None => (part, None),
+            };
+            let is_ident = name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+                && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+            is_ident.then(|| PathSegment {
+                name: name.into(),
+                index,
+            })
+        });
+        let root = parts.next()??;
+        let segments = parts.collect::<Option<_>>()?;
+        Some(Self { root, segments })
+    }
+
+    /// The first label of the path, looked up in the enclosing blocks.
+    #[must_use]
+    pub fn root(&self) -> &PathSegment {
+        &self.root
+    }
+
+    /// The labels looked up within the block of the previous label.
+    #[must_use]
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+}
+
+impl fmt::Display for LabelPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.root)?;
+        for segment in &self.segments {
+            write!(f, "::{segment}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Why a label use could not be resolved.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LabelError {
+    /// No label matches the path.
+    NotDefined,
+    /// Several labels match the path. These are the indices of the
+    /// candidates, as passed to [`Layout::label_source`].
+    Ambiguous(Vec<usize>),
+    /// A label of the path does not label a block, so it has no label
+    /// `segment`.
+    NotABlock { label: String, segment: String },
+    /// The use was resolved while laying out the data, but refers to a
+    /// different label once all of them are defined.
+    DefinedLater,
+}
+
+type RawPatchOp<E> = Box<dyn FnOnce(&LocationMap, &mut [u8]) -> Result<(), E>>;
+
+pub struct PatchOp<E>(RawPatchOp<E>);
+
+impl<E> PatchOp<E> {
+    #[must_use]
+    pub fn new<F>(f: F) -> Self
+    where
+        F: FnOnce(&LocationMap, &mut [u8]) -> Result<(), E> + 'static,
+    {
+        Self(Box::new(f))
+    }
+
+    pub fn apply(self, location_map: &LocationMap, data: &mut [u8]) -> Result<(), E> {
+        (self.0)(location_map, data)
+    }
+}
+
+/// The number of bytes reserved for each variable-size field, indexed in the
+/// order in which the fields were laid out.
+///
+/// Fields not yet listed reserve a single byte.
+#[derive(Clone, Debug, Default)]
+pub struct VarFieldSizes(Vec<usize>);
+
+impl VarFieldSizes {
+    #[must_use]
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    #[must_use]
+    pub fn get(&self, index: usize) -> usize {
+        self.0.get(index).copied().unwrap_or(1)
+    }
+
+    pub fn grow(&mut self, index: usize, size: usize) {
+        if self.0.len() <= index {
+            self.0.resize(index + 1, 1);
+        }
+        assert!(size > self.0[index], "Variable-size fields can only grow");
+        self.0[index] = size;
+    }
+}
+
+type RawVarPatchOp<E> = Box<dyn FnOnce(&LocationMap, usize) -> Result<Vec<u8>, E>>;
+
+/// A deferred patch for a field whose encoded size depends on its value.
+///
+/// The operation is given the number of bytes reserved for the field, and
+/// must produce an encoding at least that long. A longer encoding means the
+/// reservation was too small.
+pub struct VarPatchOp<E> {
+    offset: usize,
+    reserved: usize,
+    op: RawVarPatchOp<E>,
+}
+
+impl<E> VarPatchOp<E> {
+    #[must_use]
+    pub fn new<F>(offset: usize, reserved: usize, f: F) -> Self
+    where
+        F: FnOnce(&LocationMap, usize) -> Result<Vec<u8>, E> + 'static,
+    {
+        Self {
+            offset,
+            reserved,
+            op: Box::new(f),
+        }
+    }
+
+    #[must_use]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[must_use]
+    pub fn reserved(&self) -> usize {
+        self.reserved
+    }
+
+    pub fn eval(self, location_map: &LocationMap) -> Result<Vec<u8>, E> {
+        (self.op)(location_map, self.reserved)
+    }
+}
+
+/// A deferred patch whose value is computed from the generated data itself,
+/// such as a checksum.
+///
+/// These are applied after all other patches, once every label range they
+/// read from no longer contains any pending data patch other than
+/// themselves. `S` identifies the patch in errors.
+pub struct DataPatchOp<E, S> {
+    field: DataRange,
+    reads: Vec<LabelRef>,
+    source: S,
+    op: PatchOp<E>,
+}
+
+impl<E, S> DataPatchOp<E, S> {
+    #[must_use]
+    pub fn new<F>(field: DataRange, reads: Vec<LabelRef>, source: S, f: F) -> Self
+    where
+        F: FnOnce(&LocationMap, &mut [u8]) -> Result<(), E> + 'static,
+    {
+        Self {
+            field,
+            reads,
+            source,
+            op: PatchOp::new(f),
+        }
+    }
+
+    #[must_use]
+    pub fn field(&self) -> DataRange {
+        self.field
+    }
+
+    /// Returns the ranges of data this patch reads from.
+    pub fn read_ranges<'a>(
+        &'a self,
+        location_map: &'a LocationMap,
+    ) -> impl Iterator<Item = DataRange> + 'a {
+        self.reads
+            .iter()
+            .filter_map(|&label| location_map.get(label))
+    }
+
+    #[must_use]
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    pub fn apply(self, location_map: &LocationMap, data: &mut [u8]) -> Result<(), E> {
+        self.op.apply(location_map, data)
+    }
+}
+
+/// A lexical scope for labels. The whole input is a scope, as is each block,
+/// each repeat, and each iteration of a repeat.
+struct Scope {
+    parent: Option<usize>,
+    children: Vec<usize>,
+    /// The index of the iteration, if this is the scope of one iteration of a
+    /// repeat.
+    iteration: Option<usize>,
+    /// The labels defined directly in this scope.
+    labels: BTreeMap<String, usize>,
+}
+
+impl Scope {
+    fn new(parent: Option<usize>, iteration: Option<usize>) -> Self {
+        Self {
+            parent,
+            children: Vec::new(),
+            iteration,
+            labels: BTreeMap::new(),
+        }
+    }
+}
+
+struct LabelInfo<S> {
+    name: String,
+    source: S,
+    /// The scope the label is defined in.
+    scope: usize,
+    range: DataRange,
+    /// The number of elements of the block or repeat the label was attached
+    /// to, as reported with [`Layout::report_element_count`].
+    element_count: Option<usize>,
+    /// The scope of the block the label was attached to, through which
+    /// qualified paths such as `'label::inner` are resolved.
+    block_scope: Option<usize>,
+}
+
+struct LabelUse<S> {
+    path: LabelPath,
+    source: S,
+    scope: usize,
+    /// The label this use resolved to while the data was laid out, if its
+    /// value was needed then.
+    resolved_early: Option<usize>,
+}
+
+/// The state at the start of a labeled entry, returned by
+/// [`Layout::begin_label_def`].
+pub struct LabelStart {
+    label: usize,
+    num_scopes: usize,
+}
+
+/// Data being laid out, with its labels and pending patches.
+///
+/// `E` is the error returned by patches, and `S` identifies labels, label
+/// uses and data patches in errors.
+pub struct Layout<E, S> {
+    data: Vec<u8>,
+    patch_ops: Vec<PatchOp<E>>,
+    var_patch_ops: Vec<VarPatchOp<E>>,
+    data_patch_ops: Vec<DataPatchOp<E, S>>,
+    var_field_sizes: VarFieldSizes,
+    location_map: LocationMap,
+    scopes: Vec<Scope>,
+    curr_scope: usize,
+    labels: Vec<LabelInfo<S>>,
+    label_uses: Vec<LabelUse<S>>,
+    /// The number of elements of the last block or repeat applied since the
+    /// start of the innermost labeled entry.
+    element_count: Option<usize>,
+}
+
+impl<E, S> Layout<E, S> {
+    #[must_use]
+    pub fn new(var_field_sizes: VarFieldSizes) -> Self {
+        Self {
+            data: Vec::new(),
+            patch_ops: Vec::new(),
+            var_patch_ops: Vec::new(),
+            data_patch_ops: Vec::new(),
+            var_field_sizes,
+            location_map: LocationMap::new(),
+            scopes: vec![Scope::new(None, None)],
+            curr_scope: 0,
+            labels: Vec::new(),
+            label_uses: Vec::new(),
+            element_count: None,
+        }
+    }
+
+    /// Starts a scope for the labels defined within a block.
+    pub fn enter_scope(&mut self) {
+        self.push_scope(None);
+    }
+
+    /// Starts a scope for the labels defined within one iteration of a
+    /// repeat, which can be selected by its index, as in `'label[2]`.
+    pub fn enter_iteration_scope(&mut self, iteration: usize) {
+        self.push_scope(Some(iteration));
+    }
+
+    fn push_scope(&mut self, iteration: Option<usize>) {
+        let scope = self.scopes.len();
+        self.scopes
+            .push(Scope::new(Some(self.curr_scope), iteration));
+        self.scopes[self.curr_scope].children.push(scope);
+        self.curr_scope = scope;
+    }
+
+    /// Returns true if entries are being applied outside any block or repeat.
+    #[must_use]
+    pub fn at_top_level(&self) -> bool {
+        self.curr_scope == 0
+    }
+
+    pub fn exit_scope(&mut self) {
+        self.curr_scope = self.scopes[self.curr_scope]
+            .parent
+            .expect("Exited the outermost scope");
+    }
+
+    /// Defines the label `name` for an entry starting at the current offset.
+    /// Its end is set by [`report_label_def`](Self::report_label_def) once
+    /// the entry has been applied.
+    ///
+    /// If the scope already defines a label with that name, returns the
+    /// index of that label as the error.
+    pub fn begin_label_def(&mut self, name: &str, source: S) -> Result<LabelStart, usize> {
+        let index = self.labels.len();
+        match self.scopes[self.curr_scope].labels.entry(name.into()) {
+            Entry::Vacant(vacant) => {
+                vacant.insert(index);
+            }
+            Entry::Occupied(occ) => return Err(*occ.get()),
+        }
+        let offset = self.curr_offset();
+        self.labels.push(LabelInfo {
+            name: name.into(),
+            source,
+            scope: self.curr_scope,
+            range: DataRange::new(offset, offset),
+            element_count: None,
+            block_scope: None,
+        });
+        self.element_count = None;
+        Ok(LabelStart {
+            label: index,
+            num_scopes: self.scopes.len(),
+        })
+    }
+
+    pub fn report_label_def(&mut self, start: LabelStart) {
+        // If the entry is a block, its scope is the first one started since
+        // the entry began.
+        let block_scope = self
+            .scopes
+            .get(start.num_scopes)
+            .is_some_and(|scope| scope.parent == Some(self.curr_scope))
+            .then_some(start.num_scopes);
+        let end = self.curr_offset();
+        let label_info = &mut self.labels[start.label];
+        label_info.range = DataRange::new(label_info.range.start(), end);
+        label_info.element_count = self.element_count;
+        label_info.block_scope = block_scope;
+    }
+
+    /// The identifier given to the definition of the label at `index`.
+    #[must_use]
+    pub fn label_source(&self, index: usize) -> &S {
+        &self.labels[index].source
+    }
+
+    /// Records the number of elements of a block or repeat that has just been
+    /// applied, which is available through `count('label)` if it is labeled.
+    pub fn report_element_count(&mut self, count: usize) {
+        self.element_count = Some(count);
+    }
+
+    /// Discards the element count of a block or repeat applied within an
+    /// entry that is not itself one, such as a padded or length-prefixed
+    /// block, so that labeling that entry does not give it a count.
+    pub fn clear_element_count(&mut self) {
+        self.element_count = None;
+    }
+
+    /// Records a use of a label from the current scope, to be resolved by
+    /// [`resolve_label_uses`](Self::resolve_label_uses).
+    pub fn report_label_use(&mut self, path: LabelPath, source: S) -> LabelRef {
+        let label = LabelRef::new(self.label_uses.len());
+        self.label_uses.push(LabelUse {
+            path,
+            source,
+            scope: self.curr_scope,
+            resolved_early: None,
+        });
+        label
+    }
+
+    /// Returns the start of the label `path` refers to, for values needed
+    /// while laying out the data, such as the base of
+    /// `pad_to(0x40, from = 'sect)`.
+    ///
+    /// Only labels defined before this point, or labeling an enclosing entry,
+    /// are known. [`resolve_label_uses`](Self::resolve_label_uses) reports
+    /// [`LabelError::DefinedLater`] if the use refers to another label once
+    /// all of them are defined.
+    pub fn laid_out_label_start(
+        &mut self,
+        path: LabelPath,
+        source: S,
+    ) -> Result<usize, LabelError> {
+        let mut label_use = LabelUse {
+            path,
+            source,
+            scope: self.curr_scope,
+            resolved_early: None,
+        };
+        let label = self.resolve_label(&label_use)?;
+        label_use.resolved_early = Some(label);
+        self.label_uses.push(label_use);
+        Ok(self.labels[label].range.start())
+    }
+
+    /// Finds the label `name` visible from `scope`, without looking at
+    /// enclosing scopes.
+    ///
+    /// A label defined directly in the scope shadows any others. Otherwise a
+    /// label defined in a nested block is visible if it is the only one with
+    /// that name. If there are several, they are all returned as the error.
+    ///
+    /// With an `iteration`, only labels defined in that iteration of a repeat
+    /// nested in the scope are considered.
+    fn find_label(
+        &self,
+        scope: usize,
+        name: &str,
+        iteration: Option<usize>,
+    ) -> Result<Option<usize>, Vec<usize>> {
+        let mut found = Vec::new();
+        match iteration {
+            None => {
+                if let Some(&label) = self.scopes[scope].labels.get(name) {
+                    return Ok(Some(label));
+                }
+                self.find_nested_labels(scope, name, &mut found);
+            }
+            Some(iteration) => {
+                self.find_nested_labels(scope, name, &mut found);
+                found.retain(|&label| {
+                    self.iteration_within(self.labels[label].scope, scope) == Some(iteration)
+                });
+            }
+        }
+        match found.as_slice() {
+            [] => Ok(None),
+            [label] => Ok(Some(*label)),
+            _ => Err(found),
+        }
+    }
+
+    /// Returns the index of the outermost repeat iteration containing `scope`
+    /// within `ancestor`.
+    fn iteration_within(&self, mut scope: usize, ancestor: usize) -> Option<usize> {
+        let mut iteration = None;
+        while scope != ancestor {
+            let curr = &self.scopes[scope];
+            iteration = curr.iteration.or(iteration);
+            scope = curr.parent.expect("scope is not within ancestor");
+        }
+        iteration
+    }
+
+    fn find_nested_labels(&self, scope: usize, name: &str, found: &mut Vec<usize>) {
+        for &child in &self.scopes[scope].children {
+            match self.scopes[child].labels.get(name) {
+                Some(&label) => found.push(label),
+                None => self.find_nested_labels(child, name, found),
+            }
+        }
+    }
+
+    fn resolve_label(&self, label_use: &LabelUse<S>) -> Result<usize, LabelError> {
+        let path = &label_use.path;
+
+        // The first label is looked up in each enclosing scope in turn.
+        let root = path.root();
+        let mut scope = Some(label_use.scope);
+        let mut label = loop {
+            let Some(curr) = scope else {
+                return Err(LabelError::NotDefined);
+            };
+            if let Some(label) = self
+                .find_label(curr, &root.name, root.index)
+                .map_err(LabelError::Ambiguous)?
+            {
+                break label;
+            }
+            scope = self.scopes[curr].parent;
+        };
+
+        // Each following label is looked up within the block of the previous
+        // one.
+        for segment in path.segments() {
+            let Some(block_scope) = self.labels[label].block_scope else {
+                return Err(LabelError::NotABlock {
+                    label: self.labels[label].name.clone(),
+                    segment: segment.name.clone(),
+                });
+            };
+            label = self
+                .find_label(block_scope, &segment.name, segment.index)
+                .map_err(LabelError::Ambiguous)?
+                .ok_or(LabelError::NotDefined)?;
+        }
+        Ok(label)
+    }
+
+    /// Resolves every label use, returning the path and source of each one
+    /// that could not be, with the reason.
+    pub fn resolve_label_uses(&mut self) -> Vec<(&LabelPath, &S, LabelError)> {
+        let mut errors = Vec::new();
+        for (index, label_use) in self.label_uses.iter().enumerate() {
+            let resolved =
+                self.resolve_label(label_use)
+                    .and_then(|label| match label_use.resolved_early {
+                        Some(early) if early != label => Err(LabelError::DefinedLater),
+                        _ => Ok(label),
+                    });
+            match resolved {
+                Ok(label) => {
+                    let label_info = &self.labels[label];
+                    self.location_map.insert(
+                        LabelRef::new(index),
+                        label_info.range,
+                        label_info.element_count,
+                    );
+                }
+                Err(err) => errors.push((&label_use.path, &label_use.source, err)),
+            }
+        }
+        errors
+    }
+
+    /// Applies every patch except those computed from the data itself, once
+    /// label uses have been resolved.
+    ///
+    /// If some variable-size fields did not fit in the space reserved for
+    /// them, returns the sizes to lay out the data again with instead.
+    pub fn apply_patches(&mut self) -> Result<Option<VarFieldSizes>, E> {
+        // Variable-size fields are resolved first. If any of them does not fit
+        // in its reservation, every offset after it is wrong, so errors from
+        // this layout can't be trusted and are dropped until the relayout.
+        let mut grown_sizes: Option<VarFieldSizes> = None;
+        let mut var_results = Vec::new();
+        for (index, var_patch_op) in self.var_patch_ops.drain(..).enumerate() {
+            let offset = var_patch_op.offset();
+            let reserved = var_patch_op.reserved();
+            match var_patch_op.eval(&self.location_map) {
+                Ok(bytes) if bytes.len() > reserved => {
+                    grown_sizes
+                        .get_or_insert_with(|| self.var_field_sizes.clone())
+                        .grow(index, bytes.len());
+                }
+                result => var_results.push((offset, result)),
+            }
+        }
+        if grown_sizes.is_some() {
+            return Ok(grown_sizes);
+        }
+        for (offset, result) in var_results {
+            let bytes = result?;
+            self.data[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        }
+
+        for patch_op in self.patch_ops.drain(..) {
+            patch_op.apply(&self.location_map, &mut self.data)?;
+        }
+        Ok(None)
+    }
+
+    /// The patches computed from the data itself, still to be applied by
+    /// [`apply_data_patches`](Self::apply_data_patches).
+    #[must_use]
+    pub fn data_patch_ops(&self) -> &[DataPatchOp<E, S>] {
+        &self.data_patch_ops
+    }
+
+    #[must_use]
+    pub fn location_map(&self) -> &LocationMap {
+        &self.location_map
+    }
+
+    /// Applies patches computed from the data itself, such as checksums.
+    ///
+    /// A patch may only be applied once no other pending patch writes into a
+    /// range it reads from, so that e.g. a checksum over a region containing
+    /// another checksum sees its final value. A patch may read its own field,
+    /// which still holds zeroes at that point. If the pending patches depend
+    /// on each other, `circular` builds the error from their sources.
+    pub fn apply_data_patches(&mut self, circular: impl FnOnce(Vec<&S>) -> E) -> Result<(), E> {
+        while !self.data_patch_ops.is_empty() {
+            let pending = &self.data_patch_ops;
+            let ready = (0..pending.len()).find(|&i| {
+                pending[i].read_ranges(&self.location_map).all(|range| {
+                    pending
+                        .iter()
+                        .enumerate()
+                        .all(|(j, other)| i == j || !other.field().overlaps(range))
+                })
+            });
+            let Some(ready) = ready else {
+                return Err(circular(pending.iter().map(DataPatchOp::source).collect()));
+            };
+            self.data_patch_ops
+                .remove(ready)
+                .apply(&self.location_map, &mut self.data)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the laid out data, once all patches have been applied.
+    #[must_use]
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn append_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// Appends `n` bytes of padding with the value `fill`.
+    pub fn pad_bytes(&mut self, n: usize, fill: u8) {
+        let start = self.data.len();
+        self.data.resize(start + n, fill);
+    }
+
+    /// Reserves `n` zeroed bytes, to be filled in by a patch.
+    pub fn advance_bytes(&mut self, n: usize) {
+        let start = self.data.len();
+        self.data.resize(start + n, 0);
+    }
+
+    #[must_use]
+    pub fn curr_offset(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn defer_patch_op<F>(&mut self, f: F)
+    where
+        F: FnOnce(&LocationMap, &mut [u8]) -> Result<(), E> + 'static,
+    {
+        self.patch_ops.push(PatchOp::new(f));
+    }
+
+    /// Reserves space at the current offset for a field whose size depends on
+    /// its value, and defers computing its encoding until all labels are known.
+    pub fn defer_var_patch_op<F>(&mut self, f: F)
+    where
+        F: FnOnce(&LocationMap, usize) -> Result<Vec<u8>, E> + 'static,
+    {
+        let reserved = self.var_field_sizes.get(self.var_patch_ops.len());
+        let offset = self.curr_offset();
+        self.advance_bytes(reserved);
+        self.var_patch_ops
+            .push(VarPatchOp::new(offset, reserved, f));
+    }
+
+    /// Defers a patch of `field` that reads the data within the `reads` labels.
+    /// The patch is applied after all other patches; `source` is used to
+    /// report dependency errors.
+    pub fn defer_data_patch_op<F>(
+        &mut self,
+        field: DataRange,
+        reads: Vec<LabelRef>,
+        source: S,
+        f: F,
+    ) where
+        F: FnOnce(&LocationMap, &mut [u8]) -> Result<(), E> + 'static,
+    {
+        self.data_patch_ops
+            .push(DataPatchOp::new(field, reads, source, f));
+    }
+}
@@ -0,0 +1,377 @@
+#![doc = include_str!("../README.md")]
+#![no_std]
+#![forbid(unsafe_code)]
+
+extern crate alloc;
+
+mod builder;
+mod checksum;
+pub mod encoding;
+#[doc(hidden)]
+pub mod layout;
+
+pub use crate::{
+    builder::{BuildError, DataBuilder, FloatField, IntField, TextField, ValueField},
+    checksum::Checksum,
+};
+
+#[cfg(test)]
+mod tests {
+    use datalit::datalit;
+
+    use super::*;
+    use crate::encoding::{BitOrder, Endianness, FloatType, IntType, TextEncoding, VarIntType};
+
+    #[test]
+    fn matches_macro_values() {
+        let bytes = DataBuilder::new()
+            .u8(1)
+            .u16_le(2)
+            .i32_be(-3)
+            .endian(Endianness::Big)
+            .u64(4)
+            .uint(IntType::unsigned(3).le(), 0x01_02_03)
+            .int(IntType::signed(6), -2)
+            .f32_le(1.5)
+            .float(FloatType::F16.be(), 1.0)
+            .uleb(300)
+            .sleb(-2)
+            .varint(150)
+            .zigzag(-1)
+            .bits(BitOrder::MsbFirst, &[(4, 4), (5, 4)])
+            .bits(BitOrder::LsbFirst, &[(1, 1), (0x1FFF, 13), (0, 2)])
+            .build()
+            .unwrap();
+        assert_eq!(
+            bytes,
+            datalit!(
+                1u8,
+                2u16_le,
+                -3i32_be,
+                @endian = be,
+                4u64,
+                0x01_02_03u24_le,
+                -2i48,
+                1.5f32_le,
+                1.0f16_be,
+                300uleb,
+                -2sleb,
+                150varint,
+                -1zigzag,
+                bits { 4: 4, 5: 4 },
+                bits(lsb) { 1: 1, 0x1FFF: 13, 0: 2 },
+            )
+        );
+    }
+
+    #[test]
+    fn matches_macro_text() {
+        let bytes = DataBuilder::new()
+            .text("hé")
+            .text_as(TextEncoding::Utf16.le(), "hé")
+            .endian(Endianness::Big)
+            .text_as(TextEncoding::Utf32, "A")
+            .text_as(TextEncoding::Latin1, "é")
+            .string_encoding(TextEncoding::Utf16)
+            .text("hi")
+            .build()
+            .unwrap();
+        assert_eq!(
+            bytes,
+            datalit!(
+                "hé",
+                utf16le("hé"),
+                @endian = be,
+                utf32("A"),
+                latin1("é"),
+                @string_encoding = utf16,
+                "hi",
+            )
+        );
+    }
+
+    #[test]
+    fn matches_macro_layout() {
+        let bytes = DataBuilder::new()
+            .fill(0xFF)
+            .u8(1)
+            .align(4)
+            .align_with_fill(8, 0xEE)
+            .sized(4, |b| {
+                b.u16_be(0xDEAD);
+            })
+            .pad_to(0x14)
+            .pstr(IntType::U16.be(), |b| {
+                b.text("ab");
+            })
+            .zpad(3, |b| {
+                b.u8(1);
+            })
+            .build()
+            .unwrap();
+        assert_eq!(
+            bytes,
+            datalit!(
+                @fill = 0xFF,
+                1u8,
+                align(4),
+                align(8, fill = 0xEE),
+                sized(4) { 0xDEAD },
+                pad_to(0x14),
+                pstr(u16_be, "ab"),
+                zpad(1u8, 3),
+            )
+        );
+    }
+
+    #[test]
+    fn matches_macro_labels() {
+        let bytes = DataBuilder::new()
+            .label("all", |b| {
+                b.start_of("b", IntType::U8)
+                    .end_of("b", IntType::U16.be())
+                    .len_of("a", IntType::U32.le())
+                    .label("a", |b| {
+                        b.u8(0xAA).label("b", |b| {
+                            b.bytes(b"xyz");
+                        });
+                    });
+            })
+            .checksum(Checksum::Crc16Ccitt, "all", IntType::U16.be())
+            .build()
+            .unwrap();
+        assert_eq!(
+            bytes,
+            datalit!(
+                'all: {
+                    start('b): u8,
+                    end('b): u16_be,
+                    len('a): u32_le,
+                    'a: { 0xAA, 'b: b"xyz" },
+                },
+                crc16_ccitt('all): u16_be,
+            )
+        );
+    }
+
+    #[test]
+    fn matches_macro_nested_checksums() {
+        // The outer checksum covers the inner one, so it is computed last.
+        let bytes = DataBuilder::new()
+            .label("outer", |b| {
+                b.checksum(Checksum::Crc32, "inner", IntType::U32.be())
+                    .label("inner", |b| {
+                        b.bytes(b"data");
+                    });
+            })
+            .checksum(Checksum::Crc32, "outer", IntType::U32.be())
+            .build()
+            .unwrap();
+        assert_eq!(
+            bytes,
+            datalit!(
+                'outer: { crc32('inner): u32_be, 'inner: b"data" },
+                crc32('outer): u32_be,
+            )
+        );
+    }
+
+    #[test]
+    fn matches_macro_scoped_labels() {
+        // Each block is a scope, so sibling blocks may define the same label,
+        // referenced from outside through the label of its block.
+        let bytes = DataBuilder::new()
+            .len_of("first::data", IntType::U8)
+            .len_of("second::data", IntType::U8)
+            .start_of("row[1]", IntType::U8)
+            .label("first", |b| {
+                b.u8(0).label("data", |b| {
+                    b.bytes(b"ab");
+                });
+            })
+            .label("second", |b| {
+                b.len_of("data", IntType::U8).label("data", |b| {
+                    b.bytes(b"cde");
+                });
+            })
+            .labeled_repeat("rows", 3, |b, i| {
+                b.label("row", |b| {
+                    b.u8(i as u8);
+                });
+            })
+            .count_of("rows", IntType::U8)
+            .offset_of("row[2]", "rows", IntType::U8)
+            .rel_of("first", IntType::I8)
+            .block(|b| {
+                b.label("data", |b| {
+                    b.u16_le(7);
+                });
+            })
+            .label("sect", |b| {
+                b.u8(1).pad_to_from(4, "sect");
+            })
+            .build()
+            .unwrap();
+        assert_eq!(
+            bytes,
+            datalit!(
+                len('first::data): u8,
+                len('second::data): u8,
+                start('row[1]): u8,
+                'first: { 0u8, 'data: { b"ab" } },
+                'second: { len('data): u8, 'data: { b"cde" } },
+                'rows: [for i in 0..3 { 'row: { i: u8 } }],
+                count('rows): u8,
+                offset('row[2], from = 'rows): u8,
+                rel('first): i8,
+                { 'data: { 7u16_le } },
+                'sect: { 1u8, pad_to(4, from = 'sect) },
+            )
+        );
+    }
+
+    #[test]
+    fn matches_macro_var_int_values() {
+        // The values need two bytes each, which moves every label after them,
+        // so the data is laid out again.
+        let bytes = DataBuilder::new()
+            .len_of("payload", VarIntType::ULeb128)
+            .start_of("tail", VarIntType::Varint)
+            .pstr(VarIntType::ULeb128, |b| {
+                b.bytes(&[0xAB; 200]);
+            })
+            .label("payload", |b| {
+                b.bytes(&[0xCD; 130]);
+            })
+            .label("tail", |b| {
+                b.u8(1);
+            })
+            .build()
+            .unwrap();
+        assert_eq!(
+            bytes,
+            datalit!(
+                len('payload): uleb,
+                start('tail): varint,
+                pstr(uleb, [0xABu8; 200]),
+                'payload: [0xCDu8; 130],
+                'tail: 1u8,
+            )
+        );
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(
+            DataBuilder::new().len_of("missing", IntType::U8).build(),
+            Err(BuildError::UndefinedLabel("missing".into()))
+        );
+        assert_eq!(
+            DataBuilder::new()
+                .label("a", |_| {})
+                .label("a", |_| {})
+                .build(),
+            Err(BuildError::DuplicateLabel("a".into()))
+        );
+        assert_eq!(
+            DataBuilder::new()
+                .len_of("x", IntType::U8)
+                .label("a", |b| {
+                    b.label("x", |_| {});
+                })
+                .label("b", |b| {
+                    b.label("x", |_| {});
+                })
+                .build(),
+            Err(BuildError::AmbiguousLabel("x".into()))
+        );
+        assert_eq!(
+            DataBuilder::new()
+                .label("a", |_| {})
+                .len_of("a::x", IntType::U8)
+                .build(),
+            Err(BuildError::UndefinedLabel("a::x".into()))
+        );
+        assert_eq!(
+            DataBuilder::new().len_of("a b", IntType::U8).build(),
+            Err(BuildError::InvalidLabel("a b".into()))
+        );
+        assert_eq!(
+            DataBuilder::new()
+                .pad_to_from(4, "later")
+                .label("later", |_| {})
+                .build(),
+            Err(BuildError::LabelNotLaidOut("later".into()))
+        );
+        assert_eq!(
+            DataBuilder::new().int(IntType::I8, 128).build(),
+            Err(BuildError::OutOfRange {
+                value: "128".into(),
+                ty: "i8".into()
+            })
+        );
+        assert_eq!(
+            DataBuilder::new()
+                .len_of("a", IntType::U8)
+                .label("a", |b| {
+                    b.bytes(&[0; 256]);
+                })
+                .build(),
+            Err(BuildError::OutOfRange {
+                value: "256".into(),
+                ty: "u8".into()
+            })
+        );
+        assert_eq!(
+            DataBuilder::new().align(3).build(),
+            Err(BuildError::InvalidAlignment(3))
+        );
+        assert_eq!(
+            DataBuilder::new().u32(0).pad_to(2).build(),
+            Err(BuildError::PastOffset { offset: 2, len: 4 })
+        );
+        assert_eq!(
+            DataBuilder::new()
+                .sized(1, |b| {
+                    b.u16(0);
+                })
+                .build(),
+            Err(BuildError::BlockOverflow { size: 1, len: 2 })
+        );
+        assert_eq!(
+            DataBuilder::new()
+                .label("a", |b| {
+                    b.checksum(Checksum::Crc8, "b", IntType::U8);
+                })
+                .label("b", |b| {
+                    b.checksum(Checksum::Crc8, "a", IntType::U8);
+                })
+                .build(),
+            Err(BuildError::CircularDependency)
+        );
+        assert_eq!(
+            DataBuilder::new()
+                .text_as(TextEncoding::Latin1, "€")
+                .build(),
+            Err(BuildError::Unrepresentable {
+                ch: '€',
+                encoding: TextEncoding::Latin1
+            })
+        );
+        assert_eq!(
+            DataBuilder::new()
+                .bits(BitOrder::MsbFirst, &[(16, 4), (0, 4)])
+                .build(),
+            Err(BuildError::OutOfRange {
+                value: "16".into(),
+                ty: "4-bit field".into()
+            })
+        );
+        assert_eq!(
+            DataBuilder::new()
+                .bits(BitOrder::MsbFirst, &[(1, 3)])
+                .build(),
+            Err(BuildError::PartialByte { bits: 3 })
+        );
+    }
+}
@@ -2,8 +2,33 @@
 #![no_std]
 #![forbid(unsafe_code)]
 
+// The macros pass their own path to the proc macros, which reinvoke them
+// through it once template definitions are known.
+#[doc(hidden)]
+pub mod __private {
+    pub use datalit_macros::{datalit, datalit_array};
+}
+
 #[doc = include_str!("../docs/datalit.md")]
-pub use datalit_macros::datalit;
+#[macro_export]
+macro_rules! datalit {
+    ($($input:tt)*) => {
+        $crate::__private::datalit! { @__datalit_callback { $crate::datalit } $($input)* }
+    };
+}
+
+#[doc = include_str!("../docs/datalit_array.md")]
+#[macro_export]
+macro_rules! datalit_array {
+    ($($input:tt)*) => {
+        $crate::__private::datalit_array! {
+            @__datalit_callback { $crate::datalit_array } $($input)*
+        }
+    };
+}
+
+#[doc = include_str!("../docs/datalit_template.md")]
+pub use datalit_macros::datalit_template;
 
 #[cfg(test)]
 mod tests {
@@ -167,6 +192,843 @@ mod tests {
         );
     }
 
+    #[test]
+    fn supports_128_bit_ints() {
+        let bytes = datalit!(
+            0x0102_0304_0506_0708_090A_0B0C_0D0E_0F10u128_be,
+            1u128_le,
+            -2i128_be,
+        );
+        assert_eq!(
+            bytes,
+            &[
+                0x01u8, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D,
+                0x0E, 0x0F, 0x10, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+                0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFE,
+            ]
+        );
+    }
+
+    #[test]
+    fn supports_128_bit_exprs() {
+        let bytes = datalit!(len('data): u128_be, 'data: 0xAABB);
+        assert_eq!(
+            bytes,
+            &[
+                0x00u8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x02, 0xAA, 0xBB,
+            ]
+        );
+    }
+
+    #[test]
+    fn supports_arbitrary_width_ints() {
+        let bytes = datalit!(
+            0x01_02_03_04_05u40_be,
+            0x01_02_03_04_05_06u48_le,
+            -2i56_be,
+            0x0102u16_be,
+        );
+        assert_eq!(
+            bytes,
+            &[
+                0x01u8, 0x02, 0x03, 0x04, 0x05, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, 0xFF, 0xFF,
+                0xFF, 0xFF, 0xFF, 0xFF, 0xFE, 0x01, 0x02,
+            ]
+        );
+    }
+
+    #[test]
+    fn supports_arbitrary_width_exprs() {
+        let bytes = datalit!(
+            @endian = be,
+            len('data): u40,
+            end('data): i48_le,
+            'data: 0xAABBCC,
+        );
+        assert_eq!(
+            bytes,
+            &[
+                0x00u8, 0x00, 0x00, 0x00, 0x03, 0x0E, 0x00, 0x00, 0x00, 0x00, 0x00, 0xAA, 0xBB,
+                0xCC,
+            ]
+        );
+    }
+
+    #[test]
+    fn supports_float_literals() {
+        let bytes = datalit!(
+            1.5f32_le,
+            -0.0f64_be,
+            @endian = be,
+            1f32,
+            0.1f32_le,
+            -2.5e3f64_le,
+        );
+        assert_eq!(
+            bytes,
+            &[
+                0x00u8, 0x00, 0xC0, 0x3F, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3F,
+                0x80, 0x00, 0x00, 0xCD, 0xCC, 0xCC, 0x3D, 0x00, 0x00, 0x00, 0x00, 0x00, 0x88, 0xA3,
+                0xC0,
+            ]
+        );
+    }
+
+    #[test]
+    fn supports_half_precision_floats() {
+        let bytes = datalit!(
+            1.0f16_be,
+            65504.0f16_be,
+            -2.0f16_le,
+            5.960464477539063e-8f16_be,
+            1.0bf16_be,
+            3.140625bf16_le,
+        );
+        assert_eq!(
+            bytes,
+            &[
+                0x3Cu8, 0x00, 0x7B, 0xFF, 0x00, 0xC0, 0x00, 0x01, 0x3F, 0x80, 0x49, 0x40,
+            ]
+        );
+    }
+
+    #[test]
+    fn supports_float_constants() {
+        let bytes = datalit!(
+            f32_be::NAN,
+            f32_be::INFINITY,
+            f64_le::NEG_INFINITY,
+            f16_be::INFINITY,
+            @endian = le,
+            f32::NAN,
+        );
+        assert_eq!(
+            bytes,
+            &[
+                0x7Fu8, 0xC0, 0x00, 0x00, 0x7F, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0xF0, 0xFF, 0x7C, 0x00, 0x00, 0x00, 0xC0, 0x7F,
+            ]
+        );
+    }
+
+    #[test]
+    fn supports_varint_literals() {
+        let bytes = datalit!(
+            0uleb,
+            300uleb,
+            624485uleb128,
+            -2sleb,
+            -123456sleb128,
+            63sleb,
+            64sleb,
+            150varint,
+            -1varint,
+            -1zigzag,
+            2zigzag,
+        );
+        assert_eq!(
+            bytes,
+            &[
+                0x00u8, 0xAC, 0x02, 0xE5, 0x8E, 0x26, 0x7E, 0xC0, 0xBB, 0x78, 0x3F, 0xC0, 0x00,
+                0x96, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x01, 0x04,
+            ]
+        );
+    }
+
+    #[test]
+    fn supports_varint_wrappers() {
+        let bytes = datalit!(varint(150), uleb(300), sleb(-2), zigzag(-1));
+        assert_eq!(bytes, &[0x96u8, 0x01, 0xAC, 0x02, 0x7E, 0x01]);
+    }
+
+    #[test]
+    fn supports_varint_exprs() {
+        let bytes = datalit!(len('data): uleb, 'data: [0xAAu8; 200]);
+        assert_eq!(bytes.len(), 202);
+        assert_eq!(&bytes[..3], &[0xC8u8, 0x01, 0xAA]);
+
+        // The offset only needs a second byte once the field itself has
+        // grown, which pushes the target forward.
+        let bytes = datalit!(start('x): uleb, [0u8; 126], 'x: 0xAA);
+        assert_eq!(bytes.len(), 128);
+        assert_eq!(&bytes[..1], &[0x7Fu8]);
+        let bytes = datalit!(start('x): uleb, [0u8; 127], 'x: 0xAA, end('x): varint);
+        assert_eq!(bytes.len(), 132);
+        assert_eq!(&bytes[..2], &[0x81u8, 0x01]);
+        assert_eq!(&bytes[129..], &[0xAAu8, 0x82, 0x01]);
+    }
+
+    #[test]
+    fn supports_arithmetic_exprs() {
+        let bytes = datalit!(
+            'a: 0xAABB,
+            end('a) - start('b): i8,
+            len('payload) + 4: u8,
+            start('payload) / 2: u8,
+            (len('payload) * 3) % 5: u8,
+            // Expressions may start with an integer.
+            4 * len('payload): u8,
+            0x100 - len('payload): u16_be,
+            'b: 0xCC,
+            'payload: { 1u32, 2u8 },
+        );
+        assert_eq!(
+            bytes,
+            &[
+                0xAAu8, 0xBB, 0xF9, 0x09, 0x05, 0x00, 0x14, 0x00, 0xFB, 0xCC, 1, 0, 0, 0, 2
+            ]
+        );
+    }
+
+    #[test]
+    fn supports_arithmetic_precedence() {
+        let bytes = datalit!(
+            // Multiplication binds tighter than addition.
+            len('x) + 2 * 3: u8,
+            (len('x) + 2) * 3: u8,
+            // Shifts bind looser than addition.
+            len('x) << 1 + 1: u8,
+            // Bitwise operators, loosest to tightest: | ^ &
+            len('x) | 0xF0 ^ 0x30 & 0x10: u8,
+            // Subtraction is left-associative.
+            end('x) - 1 - 1: u8,
+            'x: 0x0102,
+        );
+        assert_eq!(bytes, &[8u8, 12, 8, 0xE2, 5, 0x01, 0x02]);
+    }
+
+    #[test]
+    fn supports_checksums() {
+        let bytes = datalit!(
+            @endian = be,
+            'check: b"123456789",
+            crc8('check): u8,
+            crc16_ccitt('check): u16,
+            crc16_xmodem('check): u16,
+            crc16_kermit('check): u16,
+            crc16_arc('check): u16,
+            crc16_modbus('check): u16,
+            crc32('check): u32,
+            crc32c('check): u32,
+            adler32('check): u32,
+            sum8('check): u8,
+            xor8('check): u8,
+        );
+        assert_eq!(
+            &bytes[9..],
+            &[
+                0xF4u8, 0x29, 0xB1, 0x31, 0xC3, 0x21, 0x89, 0xBB, 0x3D, 0x4B, 0x37, 0xCB, 0xF4,
+                0x39, 0x26, 0xE3, 0x06, 0x92, 0x83, 0x09, 0x1E, 0x01, 0xDE, 0xDD, 0x31,
+            ]
+        );
+    }
+
+    #[test]
+    fn supports_png_chunk_crc() {
+        let bytes = datalit!(
+            len('data): u32_be,
+            'chunk: { b"IEND", 'data: {} },
+            crc32('chunk): u32_be,
+        );
+        assert_eq!(
+            bytes,
+            &[0u8, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]
+        );
+    }
+
+    #[test]
+    fn supports_checksum_inside_its_own_range() {
+        // IPv4 header: the checksum field is zero while it is computed.
+        let bytes = datalit!(
+            @endian = be,
+            'hdr: {
+                0x4500, len('packet): u16, 0x0000, 0x4000, 0x4011,
+                inet_checksum('hdr): u16,
+                0xC0A8_0001, 0xC0A8_00C7,
+            },
+            'packet: [0x00; 95],
+        );
+        assert_eq!(&bytes[..4], &[0x45u8, 0x00, 0x00, 0x5F]);
+        assert_eq!(&bytes[10..12], &[0xB8u8, 0x75]);
+    }
+
+    #[test]
+    fn supports_nested_checksums() {
+        // The outer checksum covers the inner one, so it is computed last
+        // regardless of the order the entries appear in.
+        let bytes = datalit!(
+            crc32('outer): u32_be,
+            'outer: { b"abc", crc32('inner): u32_be, 'inner: b"xyz" },
+        );
+        assert_eq!(
+            bytes,
+            &[
+                0x3Cu8, 0x0A, 0x32, 0x7D, b'a', b'b', b'c', 0xEB, 0x8E, 0xBA, 0x67, b'x', b'y',
+                b'z',
+            ]
+        );
+    }
+
+    #[test]
+    fn supports_digests() {
+        let bytes = datalit!('data: b"abc", md5('data), sha1('data), sha256('data));
+        assert_eq!(
+            &bytes[3..19],
+            &[
+                0x90u8, 0x01, 0x50, 0x98, 0x3C, 0xD2, 0x4F, 0xB0, 0xD6, 0x96, 0x3F, 0x7D, 0x28,
+                0xE1, 0x7F, 0x72,
+            ]
+        );
+        assert_eq!(
+            &bytes[19..39],
+            &[
+                0xA9u8, 0x99, 0x3E, 0x36, 0x47, 0x06, 0x81, 0x6A, 0xBA, 0x3E, 0x25, 0x71, 0x78,
+                0x50, 0xC2, 0x6C, 0x9C, 0xD0, 0xD8, 0x9D,
+            ]
+        );
+        assert_eq!(
+            &bytes[39..],
+            &[
+                0xBAu8, 0x78, 0x16, 0xBF, 0x8F, 0x01, 0xCF, 0xEA, 0x41, 0x41, 0x40, 0xDE, 0x5D,
+                0xAE, 0x22, 0x23, 0xB0, 0x03, 0x61, 0xA3, 0x96, 0x17, 0x7A, 0x9C, 0xB4, 0x10, 0xFF,
+                0x61, 0xF2, 0x00, 0x15, 0xAD,
+            ]
+        );
+    }
+
+    #[test]
+    fn supports_digest_before_its_range() {
+        let bytes = datalit!(sha224('data), len('data): u8, 'data: b"abc");
+        assert_eq!(bytes.len(), 28 + 1 + 3);
+        assert_eq!(
+            &bytes[..4],
+            &[0x23u8, 0x09, 0x7D, 0x22],
+            "SHA-224 of \"abc\" starts with 23097d22"
+        );
+        assert_eq!(&bytes[28..], &[3u8, b'a', b'b', b'c']);
+    }
+
+    #[test]
+    fn supports_include() {
+        let bytes = datalit!(
+            len('file): u8,
+            'file: include("tests/fixtures/counting.bin", 4..8),
+            include("tests/fixtures/counting.bin", ..=1),
+            include("tests/fixtures/counting.bin", 14..),
+            crc8('file): u8,
+        );
+        assert_eq!(
+            bytes,
+            &[4u8, 0x04, 0x05, 0x06, 0x07, 0x00, 0x01, 0x0E, 0x0F, 0xF3]
+        );
+        assert_eq!(
+            datalit!(include("tests/fixtures/counting.bin")),
+            &[
+                0x00u8, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C,
+                0x0D, 0x0E, 0x0F,
+            ]
+        );
+    }
+
+    #[test]
+    fn supports_fill_byte() {
+        let bytes = datalit!(
+            1u8,
+            align(4),
+            @fill = 0xFF,
+            2u8,
+            align(4),
+            3u8,
+            align(4, fill = 0x90),
+            @fill = 0,
+            4u8,
+            align(2),
+        );
+        assert_eq!(
+            bytes,
+            &[
+                1u8, 0x00, 0x00, 0x00, 2, 0xFF, 0xFF, 0xFF, 3, 0x90, 0x90, 0x90, 4, 0x00
+            ]
+        );
+    }
+
+    #[test]
+    fn fill_byte_does_not_affect_expression_fields() {
+        // The checksum sees its own field as zero, whatever the fill byte.
+        let bytes = datalit!(
+            @fill = 0xFF,
+            @endian = be,
+            'hdr: { 0x4500_0073, 0x0000_4000, 0x4011, inet_checksum('hdr): u16, 0xC0A8_0001, 0xC0A8_00C7 },
+            len('hdr): u8,
+        );
+        assert_eq!(&bytes[10..12], &[0xB8u8, 0x61]);
+        assert_eq!(bytes[20], 20);
+    }
+
+    #[test]
+    fn supports_pad_to() {
+        let bytes = datalit!(
+            1u8,
+            pad_to(4),
+            2u8,
+            org(6),
+            @fill = 0xFF,
+            3u8,
+            pad_to(8),
+            pad_to(8),
+            pad_to(10, fill = 0xCC),
+        );
+        assert_eq!(
+            bytes,
+            &[1u8, 0x00, 0x00, 0x00, 2, 0x00, 3, 0xFF, 0xCC, 0xCC]
+        );
+    }
+
+    #[test]
+    fn supports_label_relative_pad_to() {
+        let bytes = datalit!(
+            0xAABB,
+            'hdr: { 1u8, pad_to(4, from = 'hdr) },
+            'sect: { 2u8, pad_to(1 + 2, from = 'sect, fill = 0xFF) },
+            pad_to(2 * 4 + 2, from = 'hdr),
+            [for i in 1..3 { 'rec: { i: u8, pad_to(i, from = 'rec) } }],
+        );
+        assert_eq!(
+            bytes,
+            &[
+                0xAAu8, 0xBB, 1, 0x00, 0x00, 0x00, 2, 0xFF, 0xFF, 0x00, 0x00, 0x00, 1, 2, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn supports_sized_blocks() {
+        let bytes = datalit!(
+            'sect: sized(6) { 1u8, 2u8 },
+            len('sect): u8,
+            sized(2, fill = 0xFF) { 3u8 },
+            sized(2) { 4u16_be },
+        );
+        assert_eq!(
+            bytes,
+            &[1u8, 2, 0x00, 0x00, 0x00, 0x00, 6, 3, 0xFF, 0x00, 0x04]
+        );
+    }
+
+    const MAGIC: u32 = 0x4D41_4749;
+    const VERSION: u16 = 3;
+    const OFFSET: i32 = -2;
+    const SCALE: f32 = 1.5;
+
+    #[test]
+    fn supports_interpolation() {
+        let bytes = datalit!(
+            #(MAGIC): u32_be,
+            #(VERSION): u16_le,
+            #(VERSION * 2 + 1): u16_be,
+            #(OFFSET): i24_be,
+            #(0x12_3456): u24_le,
+            #(SCALE): f32_be,
+            len('rest): u8,
+            'rest: { #(VERSION as u8): u8 },
+        );
+        assert_eq!(
+            bytes,
+            &[
+                0x4Du8, 0x41, 0x47, 0x49, 0x03, 0x00, 0x00, 0x07, 0xFF, 0xFF, 0xFE, 0x56, 0x34,
+                0x12, 0x3F, 0xC0, 0x00, 0x00, 0x01, 0x03,
+            ]
+        );
+    }
+
+    #[test]
+    fn interpolates_native_values_like_literals() {
+        assert_eq!(
+            datalit!(#(VERSION): u16_ne, @endian = ne, #(VERSION): u16),
+            datalit!(3u16_ne, 3u16_ne),
+        );
+    }
+
+    #[test]
+    fn supports_interpolation_in_const_contexts() {
+        const DATA: &[u8] = datalit!(@endian = be, #(VERSION): u16, 0xFF);
+        assert_eq!(DATA, &[0x00u8, 0x03, 0xFF]);
+    }
+
+    #[test]
+    fn supports_array_output() {
+        let array: [u8; 4] = datalit_array!(1u16_be, len('a): u8, 'a: 0xFF);
+        assert_eq!(array, [0x00u8, 0x01, 0x01, 0xFF]);
+
+        #[repr(C, align(16))]
+        struct Aligned([u8; 3]);
+        static ALIGNED: Aligned = Aligned(datalit_array!(#(VERSION): u16_le, 0xAB));
+        assert_eq!(&ALIGNED.0, &[0x03u8, 0x00, 0xAB]);
+        assert_eq!(core::ptr::from_ref(&ALIGNED.0).addr() % 16, 0);
+
+        let empty: [u8; 0] = datalit_array!();
+        assert_eq!(empty, []);
+    }
+
+    #[test]
+    fn supports_output_alignment() {
+        for bytes in [
+            datalit!(@align_output = 16, 0x01, 0x02, 0x03),
+            datalit!(0x01, @align_output = 16, #(0x0203): u16_be),
+        ] {
+            assert_eq!(bytes, &[0x01u8, 0x02, 0x03]);
+            assert_eq!(bytes.as_ptr().addr() % 16, 0);
+        }
+
+        const ALIGNED: &[u8] = datalit!(@align_output = 8, 1u64_le);
+        assert_eq!(ALIGNED, &[1u8, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(ALIGNED.as_ptr().addr() % 8, 0);
+    }
+
+    #[test]
+    fn supports_templates() {
+        datalit_template! {
+            chunk(tag, body) = {
+                len('data): u32_be,
+                'crc: { tag, 'data: body },
+                crc32('crc): u32_be,
+            };
+            pair(a, b) = { a, b }
+        }
+
+        let bytes = datalit!(
+            chunk!(b"IEND", {}),
+            chunk!(b"tEXt", { pair!(0x01, 2u16_le) }),
+        );
+        assert_eq!(
+            bytes,
+            datalit!(
+                0u32_be, 'a: b"IEND", crc32('a): u32_be,
+                3u32_be, 'b: { b"tEXt", 0x01, 2u16_le }, crc32('b): u32_be,
+            )
+        );
+
+        // Arguments may refer to labels outside the template.
+        let array: [u8; 3] = datalit_array!(pair!(len('x): u8, 'x: 0xAABB));
+        assert_eq!(array, [0x02u8, 0xAA, 0xBB]);
+
+        const NESTED: &[u8] = datalit!(pair!(pair!(1u8, 2u8), [pair!(3u8, 4u8); 2]));
+        assert_eq!(NESTED, &[1u8, 2, 3, 4, 3, 4]);
+
+        // Labels defined in the body are scoped to the invocation's block, and
+        // can be reached through a label on the invocation.
+        let bytes = datalit!(
+            len('second::data): u8,
+            chunk!(b"A", 0x01),
+            'second: chunk!(b"B", 0x0203),
+        );
+        assert_eq!(&bytes[..6], &[0x02u8, 0, 0, 0, 1, b'A']);
+        assert_eq!(bytes.len(), 1 + 10 + 11);
+    }
+
+    #[test]
+    fn template_params_may_share_builtin_names() {
+        const MAGIC: u8 = 7;
+        datalit_template! {
+            rec(len, body) = { len, len('b): u8, 'b: body };
+            padded(align, fill, u8) = { align, align(4, fill = 0xFF), fill * 1: u8, u8 };
+            magic(MAGIC) = { MAGIC, #(MAGIC): u8 }
+        }
+
+        let bytes = datalit!(rec!(0x01, b"ab"), padded!(0x02, 0x03, 0x04), magic!(0x05),);
+        assert_eq!(
+            bytes,
+            &[
+                0x01u8, 0x02, b'a', b'b', 0x02, 0xFF, 0xFF, 0xFF, 0x03, 0x04, 0x05, 0x07
+            ]
+        );
+    }
+
+    #[test]
+    fn supports_scoped_labels() {
+        let bytes = datalit!(
+            len('a::data): u8,
+            len('b::data): u8,
+            'a: { len('data): u8, 'data: 0xAAAA },
+            'b: { len('data): u8, 'data: { 0xBB, 'data: 0xCCCC } },
+        );
+        assert_eq!(
+            bytes,
+            &[0x02u8, 0x03, 0x02, 0xAA, 0xAA, 0x03, 0xBB, 0xCC, 0xCC]
+        );
+
+        // Labels in nested blocks are visible outside them unless shadowed,
+        // and paths can reach into unlabeled blocks.
+        let bytes = datalit!(
+            start('x): u8,
+            start('outer::z): u8,
+            'outer: { { 'z: 0x01 }, 'x: { 0x02, 'y: 0x03 } },
+            'y: 0x04,
+            start('y): u8,
+            start('outer::x::y): u8,
+        );
+        assert_eq!(bytes, &[0x03u8, 0x02, 0x01, 0x02, 0x03, 0x04, 0x05, 0x04]);
+    }
+
+    #[test]
+    fn supports_labels_in_repeats() {
+        // Each iteration has its own instance of the labels in the body.
+        let bytes = datalit!(
+            start('body[2]): u8,
+            len('recs::body[0]): u8,
+            'recs: [{ len('body): u16_be, 'body: { 0xAB, len('body): u8 } }; 3],
+        );
+        assert_eq!(
+            bytes,
+            &[
+                0x0Cu8, 0x02, 0x00, 0x02, 0xAB, 0x02, 0x00, 0x02, 0xAB, 0x02, 0x00, 0x02, 0xAB,
+                0x02
+            ]
+        );
+
+        // Indices select the iteration of the outermost repeat.
+        let bytes = datalit!(
+            [{ start('inner): u8, [{ 'inner: 0xCC }; 1] }; 2],
+            start('inner[1]): u8,
+        );
+        assert_eq!(bytes, &[0x01u8, 0xCC, 0x03, 0xCC, 0x03]);
+    }
+
+    #[test]
+    fn supports_repeat_exprs() {
+        assert_eq!(datalit!([0xAB; 2 * 3 - 4]), &[0xABu8, 0xAB]);
+        assert_eq!(
+            datalit!([i in 0..4 => i * 2: u8], [i in 1..=2 => (0 - i): i16_be]),
+            &[0u8, 2, 4, 6, 0xFF, 0xFF, 0xFF, 0xFE]
+        );
+        // Nested repeats can use the variables of enclosing ones.
+        assert_eq!(
+            datalit!([for i in 0..3 { i: u8, [0xEE; i] }]),
+            &[0u8, 1, 0xEE, 2, 0xEE, 0xEE]
+        );
+        // Variables combine with labels in each iteration.
+        assert_eq!(
+            datalit!([for i in 0..2 { len('b): u8, 'b: [0x00; i + 1] }]),
+            &[1u8, 0, 2, 0, 0]
+        );
+    }
+
+    #[test]
+    fn supports_count() {
+        let bytes = datalit!(
+            count('entries): u16_le,
+            count('fields): u8,
+            'entries: [{ 0xAA, 0xBB }; 3],
+            'fields: { @endian = be, 1u16, [0x00; 2], { 0x01, 0x02 } },
+        );
+        assert_eq!(
+            bytes,
+            &[
+                0x03u8, 0x00, 0x03, 0xAA, 0xBB, 0xAA, 0xBB, 0xAA, 0xBB, 0x00, 0x01, 0x00, 0x00,
+                0x01, 0x02
+            ]
+        );
+
+        // Counts can be combined with other expressions.
+        let bytes = datalit!(
+            count('recs) * 2 + 1: u8,
+            'recs: [i in 0..2 => i: u8],
+        );
+        assert_eq!(bytes, &[0x05u8, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn supports_relative_offsets() {
+        let bytes = datalit!(
+            0xFFFF,
+            here(): u8,
+            rel('target): u8,
+            'base: { 0x00, 'target: 0xAA },
+            offset('target, from = 'base): u8,
+            offset('target): u8,
+            rel('base): i8,
+        );
+        assert_eq!(
+            bytes,
+            &[0xFFu8, 0xFF, 0x02, 0x02, 0x00, 0xAA, 0x01, 0x05, 0xFC]
+        );
+
+        // Each iteration of an array is relative to its own field.
+        let bytes = datalit!([{ rel('next): u8, 0xEE, 'next: {} }; 2]);
+        assert_eq!(bytes, &[0x02u8, 0xEE, 0x02, 0xEE]);
+    }
+
+    #[test]
+    fn supports_text_strings() {
+        let bytes = datalit!("hé", utf16le("hé"), utf16be("A"), utf32le("A"), latin1("é"),);
+        assert_eq!(
+            bytes,
+            &[
+                0x68u8, 0xC3, 0xA9, 0x68, 0x00, 0xE9, 0x00, 0x00, 0x41, 0x41, 0x00, 0x00, 0x00,
+                0xE9
+            ]
+        );
+
+        // Characters outside the BMP are surrogate pairs in UTF-16.
+        assert_eq!(
+            datalit!(utf16be("🦀"), utf32be("🦀")),
+            &[0xD8u8, 0x3E, 0xDD, 0x80, 0x00, 0x01, 0xF9, 0x80]
+        );
+
+        // Plain string literals use the string encoding mode, and encodings
+        // without an explicit endianness use the endian mode.
+        let bytes = datalit!(
+            @endian = be,
+            @string_encoding = utf16,
+            "hi",
+            @string_encoding = utf16le,
+            "hi",
+            [{ len('s): u8, 's: "ab" }; 2],
+        );
+        assert_eq!(
+            bytes,
+            &[
+                0x00u8, 0x68, 0x00, 0x69, 0x68, 0x00, 0x69, 0x00, 0x04, 0x61, 0x00, 0x62, 0x00,
+                0x04, 0x61, 0x00, 0x62, 0x00
+            ]
+        );
+    }
+
+    #[test]
+    fn supports_string_helpers() {
+        let bytes = datalit!(
+            pstr(u8, b"abc"),
+            pstr(u16_be, utf16le("hi")),
+            @endian = le,
+            pstr(u16, { b"x", [0x00; 2] }),
+            pstr(uleb, [0xEE; 200]),
+        );
+        assert_eq!(
+            &bytes[..17],
+            &[
+                3u8, b'a', b'b', b'c', 0x00, 0x04, b'h', 0x00, b'i', 0x00, 0x03, 0x00, b'x', 0x00,
+                0x00, 0xC8, 0x01
+            ]
+        );
+        assert_eq!(&bytes[17..], &[0xEE; 200]);
+
+        let bytes = datalit!(
+            @fill = 0xFF,
+            fixed(6, b"name"),
+            fixed(4, "ab", fill = 0x20),
+            zpad(b"x", 3),
+            fixed(2, b"ab"),
+        );
+        assert_eq!(
+            bytes,
+            &[
+                b'n', b'a', b'm', b'e', 0xFF, 0xFF, b'a', b'b', 0x20, 0x20, b'x', 0x00, 0x00, b'a',
+                b'b'
+            ]
+        );
+
+        // Helpers nest, and their contents can be labeled.
+        let bytes = datalit!(
+            len('name): u8,
+            fixed(4, pstr(u8, 'name: b"ab")),
+            pstr(u8, pstr(u8, b"")),
+        );
+        assert_eq!(bytes, &[2u8, 2, b'a', b'b', 0x00, 1, 0]);
+    }
+
+    #[test]
+    fn supports_encoded_bytes() {
+        let bytes = datalit!(
+            hex("de ad be ef"),
+            hex("01:02:03
+                 0A0b"),
+            base64("3q2+7w=="),
+            base64("3q2+7w"),
+            base64url("3q2-7_8"),
+            base64(""),
+        );
+        assert_eq!(
+            bytes,
+            &[
+                0xDEu8, 0xAD, 0xBE, 0xEF, 0x01, 0x02, 0x03, 0x0A, 0x0B, 0xDE, 0xAD, 0xBE, 0xEF,
+                0xDE, 0xAD, 0xBE, 0xEF, 0xDE, 0xAD, 0xBE, 0xEF, 0xFF
+            ]
+        );
+
+        // Decoded bytes are ordinary data.
+        let bytes = datalit!(len('cert): u8, 'cert: base64("
+            TWFu
+            TWE=
+        "));
+        assert_eq!(bytes, &[5u8, b'M', b'a', b'n', b'M', b'a']);
+    }
+
+    #[test]
+    fn supports_bit_fields() {
+        // IPv4 version and IHL, then DSCP and ECN.
+        let bytes = datalit!(bits { 4: 4, 5: 4 }, bits { 0b101110: 6, 0: 2 });
+        assert_eq!(bytes, &[0x45u8, 0xB8]);
+
+        // Fields may span bytes.
+        let bytes = datalit!(
+            bits(msb) { 0b010: 3, 0x1FFF: 13 },
+            bits(lsb) { 1: 1, 0b10: 2, 0x1FFF: 13 },
+        );
+        assert_eq!(bytes, &[0x5F, 0xFF, 0xFD, 0xFF]);
+
+        // Values are constant expressions.
+        let bytes = datalit!([i in 0..2 => bits { i + 1: 4, 1 << 3: 4 }]);
+        assert_eq!(bytes, &[0x18u8, 0x28]);
+
+        // Wide fields.
+        let bytes = datalit!(bits {
+            1: 1,
+            0: 127,
+            0xAB: 8
+        });
+        assert_eq!(bytes[0], 0x80);
+        assert_eq!(bytes[16], 0xAB);
+    }
+
+    #[test]
+    fn supports_computed_bit_fields() {
+        // An IPv4-style header length, in 32-bit words.
+        let bytes = datalit!(
+            'hdr: {
+                bits { 4: 4, len('hdr) / 4: 4 },
+                0x00,
+                len('pkt): u16_be,
+                [0x00; 4],
+            },
+            'pkt: b"payload",
+        );
+        assert_eq!(bytes[0], 0x42);
+        assert_eq!(bytes[2..4], [0x00, 0x07]);
+
+        // A 12-bit length sharing a 16-bit word with flags, in either order.
+        let bytes = datalit!(
+            bits { 0b101: 3, 0: 1, len('data): 12 },
+            bits(lsb) { len('data): 12, 0b1010: 4 },
+            'data: [0xEE; 0x123],
+        );
+        assert_eq!(&bytes[..4], &[0xA1, 0x23, 0x23, 0xA1]);
+
+        // Fields can be computed from the data, and covered by checksums.
+        let bytes = datalit!(
+            'all: {
+                bits { crc8('payload) >> 4: 4, 0xF: 4 },
+                'payload: b"123456789",
+            },
+            sum8('all): u8,
+        );
+        // The CRC-8 of "123456789" is 0xF4.
+        assert_eq!(bytes[0], 0xFF);
+        let sum = bytes[..10].iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        assert_eq!(bytes[10], sum);
+    }
+
     // Compile test: Can be used in a constant context
     #[allow(dead_code, reason = "Compile test only")]
     const _DATA: &[u8] = datalit!(0xDE, 0xAD, 0xBE, 0xEF);